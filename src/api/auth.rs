@@ -0,0 +1,213 @@
+use crate::{
+    common::{
+        config::{Api, Token},
+        limiter::{Charge, Limiter},
+    },
+    Error, ShoelaceData,
+};
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use blake2::{Blake2s256, Digest};
+use config::{Config, File};
+use core::fmt;
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Arc};
+
+/// What a token is allowed to access
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    User,
+    Post,
+    Proxy,
+}
+
+impl Scope {
+    /// Finds the scope needed for an API route
+    fn of(path: &str) -> Option<Self> {
+        let path = path.trim_start_matches('/');
+        let path = path.strip_prefix("v1/").unwrap_or(path);
+
+        match path.split('/').next() {
+            Some("user") => Some(Self::User),
+            Some("post") => Some(Self::Post),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::fmt::Result {
+        let out = match self {
+            Scope::User => "user",
+            Scope::Post => "post",
+            Scope::Proxy => "proxy",
+        };
+
+        write!(f, "{}", out)
+    }
+}
+
+/// Token used for a request. Its name is attached to the response for logging
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Requests per minute the token can make, or zero if unlimited
+    pub rate_limit: u32,
+}
+
+/// Checks whether a request may access a scope, for routes covering several of them
+pub fn permit(client: Option<&Client>, scope: Scope, data: &ShoelaceData) -> Result<(), Error> {
+    match client {
+        Some(client) if !client.scopes.contains(&scope) => Err(Error::MissingScope(scope)),
+        None if data.config.api.auth => Err(Error::Unauthorized),
+        _ => Ok(()),
+    }
+}
+
+/// Charges a token for the extra work done by a request, beyond the one request it already paid for
+pub async fn charge(client: Option<&Client>, cost: u32, data: &ShoelaceData) -> Result<(), Error> {
+    let Some(client) = client.filter(|client| client.rate_limit > 0 && cost > 0) else {
+        return Ok(());
+    };
+
+    data.tokens
+        .limiter
+        .charge(
+            &Charge {
+                key: client.name.clone(),
+                rate: client.rate_limit,
+                burst: client.rate_limit,
+            },
+            cost,
+        )
+        .await
+        .map_err(|wait| Error::RateLimited(wait.as_secs().max(1)))
+}
+
+/// Known tokens, and how often each has been used
+#[derive(Debug, Clone, Default)]
+pub struct Tokens {
+    tokens: HashMap<Vec<u8>, Token>,
+    limiter: Limiter,
+}
+
+impl Tokens {
+    /// Loads tokens from the config, and from the token file if there's one
+    pub fn new(config: &Api) -> Result<Self, Error> {
+        let mut tokens = config.tokens.clone();
+
+        if let Some(path) = &config.token_file {
+            tokens.extend(
+                Config::builder()
+                    .add_source(File::with_name(path))
+                    .build()?
+                    .get::<Vec<Token>>("tokens")?,
+            );
+        }
+
+        Ok(Self {
+            // Tokens are looked up by their hash, so lookups don't depend on how much of one matches
+            tokens: tokens
+                .into_iter()
+                .map(|token| (Blake2s256::digest(token.token.as_bytes()).to_vec(), token))
+                .collect(),
+            limiter: Limiter::default(),
+        })
+    }
+
+    /// Finds the token a secret belongs to
+    fn find(&self, secret: &str) -> Option<&Token> {
+        self.tokens
+            .get(Blake2s256::digest(secret.as_bytes()).as_slice())
+    }
+}
+
+/// Fetches the bearer token from a request's headers
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    let (scheme, secret) = headers.get(AUTHORIZATION)?.to_str().ok()?.split_once(' ')?;
+
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then_some(secret.trim())
+}
+
+/// Checks a request's token against a scope, and against its rate limit
+async fn authorize(
+    data: &ShoelaceData,
+    scope: Option<Scope>,
+    required: bool,
+    headers: &HeaderMap,
+) -> Result<Option<Client>, Error> {
+    let token = match bearer(headers) {
+        Some(secret) => data.tokens.find(secret).ok_or(Error::Unauthorized)?,
+        None if required && scope.is_some() => return Err(Error::Unauthorized),
+        None => return Ok(None),
+    };
+
+    if let Some(scope) = scope {
+        if !token.scopes.contains(&scope) {
+            return Err(Error::MissingScope(scope));
+        }
+    }
+
+    if token.rate_limit > 0 {
+        data.tokens
+            .limiter
+            .check(&token.name, token.rate_limit, token.rate_limit)
+            .await
+            .map_err(|wait| Error::RateLimited(wait.as_secs().max(1)))?;
+    }
+
+    Ok(Some(Client {
+        name: token.name.clone(),
+        scopes: token.scopes.clone(),
+        rate_limit: token.rate_limit,
+    }))
+}
+
+/// Runs a request once authorized, and tags its response with the token's name
+async fn run(client: Option<Client>, mut request: Request, next: Next) -> Response {
+    if let Some(client) = &client {
+        request.extensions_mut().insert(client.clone());
+    }
+
+    let mut response = next.run(request).await;
+
+    if let Some(client) = client {
+        response.extensions_mut().insert(client);
+    }
+
+    response
+}
+
+/// Authenticates API requests. Tokens are required if enabled, except for the OpenAPI document.
+/// Routes without a scope of their own check them through [`permit`]
+pub async fn api(State(data): State<Arc<ShoelaceData>>, request: Request, next: Next) -> Response {
+    let scope = Scope::of(request.uri().path());
+
+    match authorize(&data, scope, data.config.api.auth, request.headers()).await {
+        Ok(client) => run(client, request, next).await,
+        Err(error) => error.into_json(),
+    }
+}
+
+/// Authenticates proxy requests. Tokens are only required if enabled and the frontend is disabled,
+/// since the frontend can't send them
+pub async fn proxy(
+    State(data): State<Arc<ShoelaceData>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let required = data.config.api.auth && !data.config.endpoint.frontend;
+
+    match authorize(&data, Some(Scope::Proxy), required, request.headers()).await {
+        Ok(client) => run(client, request, next).await,
+        Err(error) => error.into_plaintext(),
+    }
+}
@@ -0,0 +1,87 @@
+pub mod auth;
+pub mod v1;
+
+use std::{borrow::Borrow, sync::Arc};
+
+use crate::{
+    common::error::{Code, ErrorBody, ErrorDetails},
+    req, ShoelaceData,
+};
+use askama_axum::IntoResponse;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Response,
+    routing::{self, get},
+    Json, Router,
+};
+use utoipa::OpenApi;
+
+/// OpenAPI document for the versioned API
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Shoelace API",
+        description = "Versioned API for Shoelace, an alternative frontend for Threads"
+    ),
+    paths(v1::user, v1::post, v1::batch),
+    components(schemas(
+        v1::User,
+        v1::Thread,
+        v1::Post,
+        v1::Author,
+        v1::Media,
+        v1::MediaKind,
+        v1::BatchRequest,
+        v1::BatchResponse,
+        v1::UserResult,
+        v1::ThreadResult,
+        ErrorBody,
+        ErrorDetails,
+        Code
+    )),
+    tags((name = "v1", description = "First version of the API"))
+)]
+pub struct ApiDoc;
+
+/// Attaches the API module to an Axum router
+pub fn attach(enabled: bool) -> Router<Arc<ShoelaceData>> {
+    let mut routed = Router::new();
+
+    if enabled {
+        routed = routed
+            .route("/user/:id", get(user))
+            .route("/post/:id", get(post))
+            .route("/v1/user/:id", get(v1::user))
+            .route("/v1/post/:id", get(v1::post))
+            .route("/v1/batch", routing::post(v1::batch))
+            .route("/openapi.json", get(openapi))
+    }
+
+    routed
+}
+
+/// Serves the API's OpenAPI document
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// User API endpoint. Unversioned, and mirrors the scraper's own structures
+async fn user(Path(user): Path<String>, State(store): State<Arc<ShoelaceData>>) -> Response {
+    let resp = req::user(&user, store.borrow()).await;
+
+    match resp {
+        Ok(body) => (StatusCode::OK, Json(body)).into_response(),
+        Err(error) => error.into_json(),
+    }
+}
+
+/// Post API endpoint. Unversioned, and mirrors the scraper's own structures
+async fn post(Path(post): Path<String>, State(store): State<Arc<ShoelaceData>>) -> Response {
+    let resp = req::post(&post, store.borrow()).await;
+
+    match resp {
+        Ok(body) => (StatusCode::OK, Json(body)).into_response(),
+        Err(error) => error.into_json(),
+    }
+}
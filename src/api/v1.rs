@@ -0,0 +1,390 @@
+use std::{borrow::Borrow, sync::Arc};
+
+use crate::{
+    api::auth::{self, Client, Scope},
+    common::{error::ErrorDetails, limiter::Charge},
+    req, Error, ShoelaceData,
+};
+use askama_axum::IntoResponse;
+use axum::{
+    extract::{rejection::JsonRejection, Path, State},
+    http::StatusCode,
+    response::Response,
+    Extension, Json,
+};
+use chrono::DateTime;
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Most users, and most posts, a batch fetches at once
+const BATCH_CONCURRENCY: usize = 8;
+
+/// Kind of media attached to a post
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Image,
+    Video,
+}
+
+/// Media attached to a post
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Media {
+    /// Kind of media
+    pub kind: MediaKind,
+    /// Location of the media, proxied if enabled
+    pub url: String,
+    /// Location of a preview image, proxied if enabled
+    pub thumbnail_url: String,
+    /// Alternative text
+    pub alt: Option<String>,
+}
+
+/// Author of a post
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Author {
+    /// Username, without a leading @
+    pub username: String,
+    /// Location of the author's profile picture, proxied if enabled
+    pub avatar_url: String,
+    /// Whether the author is verified
+    pub verified: bool,
+}
+
+/// Single post
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Post {
+    /// Shortcode, as used in links to the post
+    pub code: String,
+    /// Author of the post
+    pub author: Author,
+    /// Publishing date
+    #[schema(format = DateTime)]
+    pub published_at: String,
+    /// Text within the post
+    pub body: String,
+    /// Media attached to the post
+    pub media: Vec<Media>,
+    /// Amount of likes
+    pub likes: u64,
+}
+
+/// Post, alongside the posts it replies to and its replies
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Thread {
+    /// Numeric identifier, as a string to avoid losing precision
+    pub id: String,
+    /// The post itself
+    pub post: Post,
+    /// Posts being replied to, from the oldest one
+    pub parents: Vec<Post>,
+    /// Replies to the post, as far as Threads loads them at first. Further replies can't be paged
+    /// through
+    pub replies: Vec<Post>,
+}
+
+/// Profile, alongside its latest posts
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct User {
+    /// Numeric identifier, as a string to avoid losing precision
+    pub id: String,
+    /// Username, without a leading @
+    pub username: String,
+    /// Display name
+    pub name: String,
+    /// Location of the profile picture, proxied if enabled
+    pub avatar_url: String,
+    /// Whether the user is verified
+    pub verified: bool,
+    /// Profile's bio
+    pub bio: String,
+    /// Amount of followers
+    pub followers: u64,
+    /// Links listed on the profile
+    pub links: Vec<String>,
+    /// Latest posts, as far as Threads loads them at first. Older posts can't be paged through
+    pub posts: Vec<Post>,
+}
+
+/// Users and posts to fetch at once
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct BatchRequest {
+    /// Usernames, without a leading @
+    pub users: Vec<String>,
+    /// Post shortcodes
+    pub posts: Vec<String>,
+}
+
+/// Outcome of fetching a user within a batch
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserResult {
+    /// Username, as requested
+    pub id: String,
+    /// Profile, if it was fetched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<User>,
+    /// Why the profile couldn't be fetched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetails>,
+}
+
+/// Outcome of fetching a post within a batch
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ThreadResult {
+    /// Shortcode, as requested
+    pub id: String,
+    /// Thread, if it was fetched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<Thread>,
+    /// Why the post couldn't be fetched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetails>,
+}
+
+/// Users and posts fetched at once, in the order they were requested
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchResponse {
+    pub users: Vec<UserResult>,
+    pub posts: Vec<ThreadResult>,
+}
+
+impl From<spools::Media> for Media {
+    fn from(media: spools::Media) -> Self {
+        Self {
+            kind: match media.kind {
+                spools::MediaKind::Image => MediaKind::Image,
+                spools::MediaKind::Video => MediaKind::Video,
+            },
+            url: media.content,
+            thumbnail_url: media.thumbnail,
+            alt: media.alt,
+        }
+    }
+}
+
+impl From<spools::Author> for Author {
+    fn from(author: spools::Author) -> Self {
+        Self {
+            username: author.username,
+            avatar_url: author.pfp,
+            verified: author.verified,
+        }
+    }
+}
+
+/// Formats a UNIX timestamp as an RFC 3339 date
+fn date(timestamp: u64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|date| date.to_rfc3339())
+        .unwrap_or_default()
+}
+
+impl From<spools::Subpost> for Post {
+    fn from(post: spools::Subpost) -> Self {
+        Self {
+            code: post.code,
+            author: post.author.into(),
+            published_at: date(post.date),
+            body: post.body,
+            media: post.media.into_iter().map(Media::from).collect(),
+            likes: post.likes,
+        }
+    }
+}
+
+impl Thread {
+    /// Builds a thread out of a post fetched through its code
+    pub fn new(post: spools::Post, code: &str) -> Self {
+        Self {
+            id: post.id,
+            post: Post {
+                code: code.to_string(),
+                author: post.author.into(),
+                published_at: date(post.date),
+                body: post.body,
+                media: post.media.into_iter().map(Media::from).collect(),
+                likes: post.likes,
+            },
+            parents: post.parents.into_iter().map(Post::from).collect(),
+            replies: post.replies.into_iter().map(Post::from).collect(),
+        }
+    }
+}
+
+impl User {
+    /// Builds a profile out of a user fetched through its username
+    pub fn new(user: spools::User, username: &str) -> Self {
+        Self {
+            id: user.id.to_string(),
+            username: username.to_string(),
+            name: user.name,
+            avatar_url: user.pfp,
+            verified: user.verified,
+            bio: user.bio,
+            followers: user.followers,
+            links: user.links,
+            posts: user.posts.into_iter().map(Post::from).collect(),
+        }
+    }
+}
+
+/// Fetches a user's profile and latest posts
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/{username}",
+    params(("username" = String, Path, description = "Username, without a leading @")),
+    responses(
+        (status = 200, description = "Profile was found", body = User),
+        (status = 404, description = "Profile doesn't exist", body = ErrorBody),
+        (status = 401, description = "Token is missing or invalid", body = ErrorBody),
+        (status = 429, description = "Token went over its rate limit", body = ErrorBody),
+        (status = 502, description = "Threads couldn't be reached", body = ErrorBody),
+    ),
+    tag = "v1"
+)]
+pub async fn user(Path(user): Path<String>, State(store): State<Arc<ShoelaceData>>) -> Response {
+    let resp = req::user(&user, store.borrow()).await;
+
+    match resp {
+        Ok(body) => (StatusCode::OK, Json(User::new(body, &user))).into_response(),
+        Err(error) => error.into_json(),
+    }
+}
+
+/// Fetches a post, alongside the posts it replies to and its replies
+#[utoipa::path(
+    get,
+    path = "/api/v1/post/{code}",
+    params(("code" = String, Path, description = "Shortcode for the post")),
+    responses(
+        (status = 200, description = "Post was found", body = Thread),
+        (status = 404, description = "Post doesn't exist", body = ErrorBody),
+        (status = 401, description = "Token is missing or invalid", body = ErrorBody),
+        (status = 429, description = "Token went over its rate limit", body = ErrorBody),
+        (status = 502, description = "Threads couldn't be reached", body = ErrorBody),
+    ),
+    tag = "v1"
+)]
+pub async fn post(Path(post): Path<String>, State(store): State<Arc<ShoelaceData>>) -> Response {
+    let resp = req::post(&post, store.borrow()).await;
+
+    match resp {
+        Ok(body) => (StatusCode::OK, Json(Thread::new(body, &post))).into_response(),
+        Err(error) => error.into_json(),
+    }
+}
+
+/// Fetches several users and posts at once. Items failing to load don't fail the whole batch
+#[utoipa::path(
+    post,
+    path = "/api/v1/batch",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Batch was processed", body = BatchResponse),
+        (status = 400, description = "Batch is malformed, or holds too many items", body = ErrorBody),
+        (status = 401, description = "Token is missing or invalid", body = ErrorBody),
+        (status = 403, description = "Token can't fetch users or posts", body = ErrorBody),
+        (status = 429, description = "Token went over its rate limit", body = ErrorBody),
+    ),
+    tag = "v1"
+)]
+pub async fn batch(
+    State(store): State<Arc<ShoelaceData>>,
+    client: Option<Extension<Client>>,
+    charge: Option<Extension<Charge>>,
+    request: Result<Json<BatchRequest>, JsonRejection>,
+) -> Response {
+    let request = match request {
+        Ok(Json(request)) => request,
+        Err(rejection) => return Error::from(rejection).into_json(),
+    };
+
+    let client = client.as_ref().map(|Extension(client)| client);
+
+    if client.is_none() && store.config.api.auth {
+        return Error::Unauthorized.into_json();
+    }
+
+    let allowed = [
+        (!request.users.is_empty(), Scope::User),
+        (!request.posts.is_empty(), Scope::Post),
+    ]
+    .into_iter()
+    .filter(|(requested, _)| *requested)
+    .try_for_each(|(_, scope)| auth::permit(client, scope, &store));
+
+    if let Err(error) = allowed {
+        return error.into_json();
+    }
+
+    let items = request.users.len() + request.posts.len();
+    let mut max = store.config.api.max_batch;
+
+    // Every item counts as a request against the client's limits, so batches can't get around them
+    if let Some(Extension(charge)) = &charge {
+        max = max.min(charge.burst as usize);
+    }
+
+    if let Some(client) = client.filter(|client| client.rate_limit > 0) {
+        max = max.min(client.rate_limit as usize);
+    }
+
+    if items > max {
+        return Error::BatchTooLarge(max).into_json();
+    }
+
+    // The request itself was already charged for
+    let cost = items.saturating_sub(1) as u32;
+
+    if let Err(error) = auth::charge(client, cost, &store).await {
+        return error.into_json();
+    }
+
+    if let Some(Extension(charge)) = &charge {
+        if let Err(wait) = store.clients.charge(charge, cost).await {
+            return Error::RateLimited(wait.as_secs().max(1)).into_json();
+        }
+    }
+
+    let users = stream::iter(request.users)
+        .map(|id| async {
+            match req::user(&id, store.borrow()).await {
+                Ok(user) => UserResult {
+                    user: Some(User::new(user, &id)),
+                    error: None,
+                    id,
+                },
+                Err(error) => UserResult {
+                    user: None,
+                    error: Some(error.details()),
+                    id,
+                },
+            }
+        })
+        .buffered(BATCH_CONCURRENCY)
+        .collect::<Vec<UserResult>>();
+
+    let posts = stream::iter(request.posts)
+        .map(|id| async {
+            match req::post(&id, store.borrow()).await {
+                Ok(post) => ThreadResult {
+                    thread: Some(Thread::new(post, &id)),
+                    error: None,
+                    id,
+                },
+                Err(error) => ThreadResult {
+                    thread: None,
+                    error: Some(error.details()),
+                    id,
+                },
+            }
+        })
+        .buffered(BATCH_CONCURRENCY)
+        .collect::<Vec<ThreadResult>>();
+
+    let (users, posts) = futures::join!(users, posts);
+
+    (StatusCode::OK, Json(BatchResponse { users, posts })).into_response()
+}
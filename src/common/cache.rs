@@ -0,0 +1,96 @@
+use crate::ShoelaceData;
+use core::fmt;
+use serde::{de::DeserializeOwned, Serialize};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Kinds of responses which can be cached
+#[derive(Debug, Clone, Copy)]
+pub enum Kind {
+    User,
+    Post,
+}
+
+impl Kind {
+    /// Fetches the configured lifetime for this kind of response
+    fn ttl(&self, data: &ShoelaceData) -> Option<Duration> {
+        let config = &data.config.cache;
+
+        let secs = match self {
+            Kind::User => config.user_ttl,
+            Kind::Post => config.post_ttl,
+        };
+
+        if config.enabled && secs > 0 {
+            Some(Duration::from_secs(secs))
+        } else {
+            None
+        }
+    }
+
+    /// Generates the keystore key for a response
+    fn key(&self, id: &str) -> String {
+        format!("cache:{}:{}", self, id)
+    }
+}
+
+/// Fetches a response from cache, if present
+pub async fn get<T: DeserializeOwned>(kind: Kind, id: &str, data: &ShoelaceData) -> Option<T> {
+    kind.ttl(data)?;
+
+    let value = match data.store.get(&kind.key(id)).await {
+        Ok(value) => value,
+        Err(error) => {
+            warn!("Couldn't read {} {} from cache: {}", kind, id, error);
+            return None;
+        }
+    };
+
+    match value.map(|value| serde_json::from_str::<T>(&value)) {
+        Some(Ok(object)) => {
+            info!("Cache hit for {} {}", kind, id);
+            Some(object)
+        }
+        Some(Err(error)) => {
+            warn!(
+                "Discarding malformed cache entry for {} {}: {}",
+                kind, id, error
+            );
+            None
+        }
+        None => {
+            info!("Cache miss for {} {}", kind, id);
+            None
+        }
+    }
+}
+
+/// Stores a response in cache
+pub async fn set<T: Serialize>(kind: Kind, id: &str, object: &T, data: &ShoelaceData) {
+    let Some(ttl) = kind.ttl(data) else {
+        return;
+    };
+
+    let value = match serde_json::to_string(object) {
+        Ok(value) => value,
+        Err(error) => {
+            warn!("Couldn't serialize {} {} for cache: {}", kind, id, error);
+            return;
+        }
+    };
+
+    if let Err(error) = data.store.set(&kind.key(id), &value, Some(ttl)).await {
+        warn!("Couldn't store {} {} in cache: {}", kind, id, error);
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::fmt::Result {
+        let out = match self {
+            Kind::User => "user",
+            Kind::Post => "post",
+        };
+
+        write!(f, "{}", out)
+    }
+}
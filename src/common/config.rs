@@ -1,6 +1,9 @@
-use std::env;
+use std::{collections::HashMap, env};
 
-use crate::proxy::Backends;
+use crate::{
+    api::auth::Scope,
+    proxy::{Backends, Modes},
+};
 use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
 use std::fs::metadata;
@@ -14,7 +17,11 @@ use std::fs::metadata;
 pub struct Settings {
     pub server: Server,
     pub endpoint: Endpoint,
+    pub api: Api,
     pub proxy: Proxy,
+    pub cache: Cache,
+    pub feeds: Feeds,
+    pub limits: Limits,
     pub logging: Logging,
 }
 
@@ -40,10 +47,34 @@ pub struct Endpoint {
     pub rss: bool,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct Api {
+    pub auth: bool,
+    pub max_batch: usize,
+    pub token_file: Option<String>,
+    pub tokens: Vec<Token>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Token {
+    pub name: String,
+    pub token: String,
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub rate_limit: u32,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Proxy {
     pub backend: Backends,
+    pub mode: Modes,
+    pub secret: Option<String>,
+    pub allowed_hosts: Vec<String>,
+    pub ttl: u64,
+    pub max_entries: usize,
     pub redis: Option<Redis>,
+    pub sqlite: Option<Sqlite>,
+    pub cache: Option<MediaCache>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -51,6 +82,58 @@ pub struct Redis {
     pub uri: String,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct Sqlite {
+    pub path: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MediaCache {
+    pub enabled: bool,
+    pub directory: String,
+    pub max_size: u64,
+    pub ttl: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Cache {
+    pub enabled: bool,
+    pub user_ttl: u64,
+    pub post_ttl: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Feeds {
+    pub max_age: u64,
+    pub max_users: usize,
+    pub max_items: usize,
+    pub lists: HashMap<String, Vec<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Limits {
+    pub trust_forwarded: bool,
+    pub frontend: Limit,
+    pub api: Limit,
+    pub rss: Limit,
+    pub proxy: Limit,
+}
+
+impl Limits {
+    /// Checks whether any set of routes is limited
+    pub fn enabled(&self) -> bool {
+        [&self.frontend, &self.api, &self.rss, &self.proxy]
+            .iter()
+            .any(|limit| limit.rate > 0)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Limit {
+    pub rate: u32,
+    pub burst: u32,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Logging {
     pub level: String,
@@ -83,7 +166,33 @@ impl Settings {
             .set_default("endpoint.frontend", true)?
             .set_default("endpoint.api", true)?
             .set_default("endpoint.rss", true)?
+            .set_default("api.auth", false)?
+            .set_default("api.max_batch", 50)?
+            .set_default("api.tokens", Vec::<String>::new())?
             .set_default("proxy.backend", "internal")?
+            .set_default("proxy.mode", "keystore")?
+            .set_default(
+                "proxy.allowed_hosts",
+                vec!["*.cdninstagram.com", "*.fbcdn.net"],
+            )?
+            .set_default("proxy.ttl", 86400)?
+            .set_default("proxy.max_entries", 100000)?
+            .set_default("cache.enabled", true)?
+            .set_default("cache.user_ttl", 300)?
+            .set_default("cache.post_ttl", 300)?
+            .set_default("feeds.max_age", 300)?
+            .set_default("feeds.max_users", 50)?
+            .set_default("feeds.max_items", 100)?
+            .set_default("feeds.lists", HashMap::<String, Vec<String>>::new())?
+            .set_default("limits.trust_forwarded", false)?
+            .set_default("limits.frontend.rate", 0)?
+            .set_default("limits.frontend.burst", 20)?
+            .set_default("limits.api.rate", 0)?
+            .set_default("limits.api.burst", 20)?
+            .set_default("limits.rss.rate", 0)?
+            .set_default("limits.rss.burst", 10)?
+            .set_default("limits.proxy.rate", 0)?
+            .set_default("limits.proxy.burst", 200)?
             .set_default("logging.level", "info")?
             .set_default("logging.log_ips", false)?
             .set_default("logging.log_cdn", false)?
@@ -1,16 +1,85 @@
-use std::{fmt::Display, time::SystemTimeError};
+use std::{fmt::Display, sync::Arc, time::SystemTimeError};
 
 use askama::Template;
 use axum::{
-    http::StatusCode,
+    extract::rejection::JsonRejection,
+    http::{
+        header::{RETRY_AFTER, WWW_AUTHENTICATE},
+        HeaderMap, HeaderValue, StatusCode,
+    },
     response::{IntoResponse, Response},
+    Json,
 };
 use config::ConfigError;
+use serde::Serialize;
 use spools::SpoolsError;
 use thiserror::Error;
 use tracing_log::log::SetLoggerError;
+use utoipa::ToSchema;
 
-use crate::frontend::Base;
+use crate::{api::auth::Scope, frontend::Base, REQUEST_ID};
+
+/// Machine-readable error codes, as returned by the API
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Code {
+    /// Requested object doesn't exist
+    NotFound,
+    /// Request is malformed, or goes over a limit
+    BadRequest,
+    /// Request lacks a valid token
+    Unauthorized,
+    /// Request isn't allowed
+    Forbidden,
+    /// Client went over its rate limit
+    RateLimited,
+    /// Threads or its CDN are rate limiting this instance
+    UpstreamRateLimited,
+    /// Threads or its CDN returned an error, or couldn't be reached
+    UpstreamUnavailable,
+    /// Threads or its CDN took too long to respond
+    UpstreamTimeout,
+    /// A component Shoelace depends on is unavailable
+    ServiceUnavailable,
+    /// Proxying media failed
+    ProxyFailed,
+    /// Something went wrong within Shoelace itself
+    Internal,
+}
+
+impl Code {
+    /// HTTP status for responses with this code
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Code::NotFound => StatusCode::NOT_FOUND,
+            Code::BadRequest => StatusCode::BAD_REQUEST,
+            Code::Unauthorized => StatusCode::UNAUTHORIZED,
+            Code::Forbidden => StatusCode::FORBIDDEN,
+            Code::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Code::UpstreamRateLimited | Code::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Code::UpstreamUnavailable => StatusCode::BAD_GATEWAY,
+            Code::UpstreamTimeout => StatusCode::GATEWAY_TIMEOUT,
+            Code::ProxyFailed | Code::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Details for an error returned by the API
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorDetails {
+    /// Machine-readable error code
+    pub code: Code,
+    /// Human-readable description of the error
+    pub message: String,
+    /// Identifier for the request, also returned in the X-Request-Id header
+    pub request_id: Option<String>,
+}
+
+/// Envelope for errors returned by the API
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: ErrorDetails,
+}
 
 #[derive(Error, Debug)]
 pub enum TimerError {
@@ -49,24 +118,91 @@ pub enum Error {
     Pattern(#[from] regex::Error),
     #[error("not found")]
     NotFound,
+    #[error("too many users, up to {0} can be merged")]
+    TooManyUsers(usize),
+    #[error("couldn't process OPML document: {0}")]
+    Opml(#[from] quick_xml::Error),
+    #[error("no feeds found in OPML document")]
+    NoFeeds,
+    #[error("missing or invalid token")]
+    Unauthorized,
+    #[error("token isn't allowed to access {0}s")]
+    MissingScope(Scope),
+    #[error("too many requests, try again in {0} seconds")]
+    RateLimited(u64),
+    #[error("{0}")]
+    Shared(Arc<Error>),
+    #[error("too many items, up to {0} can be fetched at once")]
+    BatchTooLarge(usize),
+    #[error("invalid request body: {0}")]
+    InvalidBody(#[from] JsonRejection),
 }
 
 impl Error {
     pub fn into_plaintext(self) -> Response {
-        let status = match self {
-            Error::Threads(SpoolsError::NotFound(_)) => StatusCode::NOT_FOUND,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        let status = self.status();
+
+        (status, self.headers(), self.to_string()).into_response()
+    }
+
+    /// Builds a JSON error envelope, for use within the API
+    pub fn into_json(self) -> Response {
+        let body = ErrorBody {
+            error: self.details(),
         };
 
-        (status, self.to_string()).into_response()
+        (self.status(), self.headers(), Json(body)).into_response()
     }
 
-    fn status(&self) -> StatusCode {
+    /// Describes the error for API clients
+    pub fn details(&self) -> ErrorDetails {
+        ErrorDetails {
+            code: self.code(),
+            message: self.to_string(),
+            request_id: REQUEST_ID.try_with(|id| id.clone()).ok(),
+        }
+    }
+
+    /// Machine-readable code for the error
+    pub fn code(&self) -> Code {
         match self {
-            Error::Threads(SpoolsError::NotFound(_)) | Error::NotFound => StatusCode::NOT_FOUND,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Threads(SpoolsError::NotFound(_)) | Error::NotFound => Code::NotFound,
+            // Anything else the scraper runs into comes from Threads itself
+            Error::Threads(_) => Code::UpstreamUnavailable,
+            Error::Proxy(error) => error.code(),
+            Error::TooManyUsers(_)
+            | Error::Opml(_)
+            | Error::NoFeeds
+            | Error::BatchTooLarge(_)
+            | Error::InvalidBody(_) => Code::BadRequest,
+            Error::Unauthorized => Code::Unauthorized,
+            Error::MissingScope(_) => Code::Forbidden,
+            Error::RateLimited(_) => Code::RateLimited,
+            Error::Shared(error) => error.code(),
+            _ => Code::Internal,
         }
     }
+
+    /// Headers telling clients how to deal with the error
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        match self {
+            Error::Unauthorized => {
+                headers.insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            }
+            Error::RateLimited(wait) => {
+                headers.insert(RETRY_AFTER, HeaderValue::from(*wait));
+            }
+            _ => (),
+        }
+
+        headers
+    }
+
+    fn status(&self) -> StatusCode {
+        self.code().status()
+    }
 }
 
 impl IntoResponse for Error {
@@ -92,6 +228,6 @@ impl IntoResponse for Error {
             }
         }
 
-        (status, body).into_response()
+        (status, self.headers(), body).into_response()
     }
 }
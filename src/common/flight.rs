@@ -0,0 +1,72 @@
+use crate::Error;
+use futures::{
+    future::{BoxFuture, Shared},
+    Future, FutureExt, TryFutureExt,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Fetch which may be awaited by several requests at once
+type Flight<T> = Shared<BoxFuture<'static, Result<T, Arc<Error>>>>;
+
+/// Deduplicates concurrent fetches for the same object, so they share a single upstream request
+#[derive(Debug, Clone)]
+pub struct Flights<T> {
+    inflight: Arc<Mutex<HashMap<String, Flight<T>>>>,
+}
+
+impl<T> Default for Flights<T> {
+    fn default() -> Self {
+        Self {
+            inflight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Flights<T> {
+    /// Runs a fetch for an object, unless one is already in flight, in which case its result is
+    /// shared instead
+    pub async fn run<F>(&self, key: &str, fetch: F) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>> + Send + 'static,
+    {
+        let flight = {
+            let mut inflight = self
+                .inflight
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+
+            match inflight.get(key) {
+                Some(flight) => flight.clone(),
+                None => {
+                    let (owned, flights) = (key.to_string(), self.inflight.clone());
+
+                    let flight = async move {
+                        let result = fetch.map_err(Arc::new).await;
+
+                        // Whoever finishes the fetch lets the next one through, even if the
+                        // request that started it went away
+                        flights
+                            .lock()
+                            .unwrap_or_else(|error| error.into_inner())
+                            .remove(&owned);
+
+                        result
+                    }
+                    .boxed()
+                    .shared();
+
+                    inflight.insert(key.to_string(), flight.clone());
+                    flight
+                }
+            }
+        };
+
+        // Errors can only be handed back as they were if nobody else is waiting on them
+        flight
+            .await
+            .map_err(|error| Arc::try_unwrap(error).unwrap_or_else(Error::Shared))
+    }
+}
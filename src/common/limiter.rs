@@ -0,0 +1,94 @@
+use lru::LruCache;
+use std::{
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// Most buckets kept, dropping the least recently used ones beyond that
+const MAX_BUCKETS: NonZeroUsize = match NonZeroUsize::new(10000) {
+    Some(max) => max,
+    None => unreachable!(),
+};
+
+/// Token bucket, refilled continuously
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    per_second: f64,
+    burst: f64,
+}
+
+impl Bucket {
+    /// Counts the tokens held at a given time
+    fn refilled(&self, now: Instant) -> f64 {
+        (self.tokens + now.duration_since(self.updated).as_secs_f64() * self.per_second)
+            .min(self.burst)
+    }
+}
+
+/// Bucket a request was charged to, so handlers doing more work than usual can charge it further
+#[derive(Debug, Clone)]
+pub struct Charge {
+    pub key: String,
+    pub rate: u32,
+    pub burst: u32,
+}
+
+/// Rate limiter, holding a token bucket for each client
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    buckets: Arc<Mutex<LruCache<String, Bucket>>>,
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self {
+            buckets: Arc::new(Mutex::new(LruCache::new(MAX_BUCKETS))),
+        }
+    }
+}
+
+impl Limiter {
+    /// Takes a token from a client's bucket, which refills `rate` tokens per minute and holds up
+    /// to `burst` of them. Returns how long to wait for the next token if the bucket is empty
+    pub async fn check(&self, key: &str, rate: u32, burst: u32) -> Result<(), Duration> {
+        self.take(key, rate, burst, 1).await
+    }
+
+    /// Takes several tokens from the bucket a request was already charged to
+    pub async fn charge(&self, charge: &Charge, cost: u32) -> Result<(), Duration> {
+        self.take(&charge.key, charge.rate, charge.burst, cost)
+            .await
+    }
+
+    /// Takes tokens from a client's bucket, if it holds enough of them
+    async fn take(&self, key: &str, rate: u32, burst: u32, cost: u32) -> Result<(), Duration> {
+        let cost = f64::from(cost);
+        let now = Instant::now();
+        let per_second = f64::from(rate) / 60.0;
+        let burst = f64::from(burst.max(1));
+
+        let mut buckets = self.buckets.lock().await;
+
+        // Dropped buckets start over full, which only lets the least active clients through early
+        let bucket = buckets.get_or_insert_mut(key.to_string(), || Bucket {
+            tokens: burst,
+            updated: now,
+            per_second,
+            burst,
+        });
+
+        bucket.tokens = bucket.refilled(now);
+        bucket.updated = now;
+
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((cost - bucket.tokens) / per_second))
+        }
+    }
+}
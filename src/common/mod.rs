@@ -1,3 +1,6 @@
+pub mod cache;
 pub mod config;
 pub mod error;
+pub mod flight;
+pub mod limiter;
 pub mod req;
@@ -1,23 +1,64 @@
-use crate::{proxy, Error, ShoelaceData};
+use crate::{
+    common::{
+        cache::{self, Kind},
+        flight::Flights,
+    },
+    proxy, Error, ShoelaceData,
+};
 use futures::future::join_all;
 use spools::{Media, Post, Threads, User};
+use tracing::warn;
+
+/// Proxies a media URL. URLs on hosts outside the allowlist are blanked, so a single one doesn't
+/// take down the whole response
+pub async fn proxied(url: &str, store: &ShoelaceData) -> Result<String, proxy::Error> {
+    match proxy::store(url, store.clone()).await {
+        Err(proxy::Error::ForbiddenHost(host)) => {
+            warn!("Dropping media from {}, as its host isn't allowed", host);
+            Ok(String::new())
+        }
+        result => result,
+    }
+}
 
 /// Common function for storing media structs
 async fn media_store(media: &mut Media, store: &ShoelaceData) -> Result<(), proxy::Error> {
-    media.content = proxy::store(&media.content, store.clone()).await?;
+    media.content = proxied(&media.content, store).await?;
 
-    media.thumbnail = proxy::store(&media.thumbnail, store.clone()).await?;
+    media.thumbnail = proxied(&media.thumbnail, store).await?;
 
     Ok(())
 }
 
+/// Fetches in flight, shared by concurrent requests for the same object
+#[derive(Debug, Clone, Default)]
+pub struct Inflight {
+    users: Flights<User>,
+    posts: Flights<Post>,
+}
+
 /// Fetches a user, and proxies its media
 #[tracing::instrument(err(Display), skip(user, store), fields(error))]
 pub async fn user<'a>(user: &'a str, store: &ShoelaceData) -> Result<User, Error> {
+    if let Some(cached) = cache::get(Kind::User, user, store).await {
+        return Ok(cached);
+    }
+
+    let (id, data) = (user.to_string(), store.clone());
+
+    store
+        .inflight
+        .users
+        .run(user, async move { fetch_user(&id, &data).await })
+        .await
+}
+
+/// Fetches a user from Threads, proxying its media and caching it
+async fn fetch_user(user: &str, store: &ShoelaceData) -> Result<User, Error> {
     let thread = Threads::new()?;
     let mut resp = thread.fetch_user(user).await?;
 
-    let pfp = proxy::store(resp.pfp.as_str(), store.clone()).await?;
+    let pfp = proxied(resp.pfp.as_str(), store).await?;
     resp.pfp.clone_from(&pfp);
 
     join_all(resp.posts.iter_mut().map(|sub| {
@@ -34,15 +75,32 @@ pub async fn user<'a>(user: &'a str, store: &ShoelaceData) -> Result<User, Error
     }))
     .await;
 
+    cache::set(Kind::User, user, &resp, store).await;
+
     Ok(resp)
 }
 
 /// Fetches a post, and proxies its media
 #[tracing::instrument(err(Display), skip(post, store), fields(error))]
 pub async fn post<'a>(post: &'a str, store: &ShoelaceData) -> Result<Post, Error> {
+    if let Some(cached) = cache::get(Kind::Post, post, store).await {
+        return Ok(cached);
+    }
+
+    let (code, data) = (post.to_string(), store.clone());
+
+    store
+        .inflight
+        .posts
+        .run(post, async move { fetch_post(&code, &data).await })
+        .await
+}
+
+/// Fetches a post from Threads, proxying its media and caching it
+async fn fetch_post(post: &str, store: &ShoelaceData) -> Result<Post, Error> {
     let thread = Threads::new()?;
     let mut resp = thread.fetch_post(post).await?;
-    resp.author.pfp = proxy::store(&resp.author.pfp, store.clone()).await?;
+    resp.author.pfp = proxied(&resp.author.pfp, store).await?;
 
     join_all(resp.media.iter_mut().map(|object| async {
         media_store(object, store).await?;
@@ -51,7 +109,7 @@ pub async fn post<'a>(post: &'a str, store: &ShoelaceData) -> Result<Post, Error
     .await;
 
     join_all(resp.parents.iter_mut().map(|sub| async {
-        sub.author.pfp = proxy::store(&sub.author.pfp, store.to_owned()).await?;
+        sub.author.pfp = proxied(&sub.author.pfp, store).await?;
         join_all(sub.media.iter_mut().map(|object| async {
             media_store(object, store).await?;
             Ok::<(), proxy::Error>(())
@@ -62,7 +120,7 @@ pub async fn post<'a>(post: &'a str, store: &ShoelaceData) -> Result<Post, Error
     .await;
 
     join_all(resp.replies.iter_mut().map(|sub| async {
-        sub.author.pfp = proxy::store(&sub.author.pfp, store.to_owned()).await?;
+        sub.author.pfp = proxied(&sub.author.pfp, store).await?;
         join_all(sub.media.iter_mut().map(|object| async {
             media_store(object, store).await?;
             Ok::<(), proxy::Error>(())
@@ -72,5 +130,7 @@ pub async fn post<'a>(post: &'a str, store: &ShoelaceData) -> Result<Post, Error
     }))
     .await;
 
+    cache::set(Kind::Post, post, &resp, store).await;
+
     Ok(resp)
 }
@@ -1,3 +1,4 @@
+use askama::{Html, MarkupDisplay};
 use linkify::LinkFinder;
 use numfmt::{Formatter, Precision, Scales};
 use regex::Regex;
@@ -7,13 +8,24 @@ use crate::{frontend::templates::Base, Error};
 pub(super) fn link(link: &str) -> String {
     format!(
         "<a href=\"{}\">{}</a>",
-        link,
-        link.trim_start_matches("http://")
-            .trim_start_matches("https://")
-            .trim_end_matches('/')
+        escape(link),
+        escape(
+            link.trim_start_matches("http://")
+                .trim_start_matches("https://")
+                .trim_end_matches('/')
+        )
     )
 }
 
+/// Requests a thumbnail-sized variant of proxied images, if variants get cached
+pub(super) fn thumbnail(url: &str, size: u32, base: &Base) -> String {
+    if base.thumbnails && url.starts_with(&format!("{}/proxy/", base.url)) {
+        format!("{}?w={}&h={}", url, size, size)
+    } else {
+        url.to_string()
+    }
+}
+
 pub(super) fn number(value: u64) -> String {
     let format: String = if value >= 10 {
         let mut formatter = Formatter::new()
@@ -33,41 +45,38 @@ pub(super) fn number(value: u64) -> String {
     format.to_owned()
 }
 
-pub(super) fn body(body: &str, base: &Base) -> Result<String, Error> {
-    let mut inner_body = body.to_string();
-    let mut offset: isize = 0;
-    let finder = LinkFinder::new();
-
-    finder.links(inner_body.clone().as_str()).for_each(|l| {
-        let left = &inner_body[..(l.start() as isize + offset) as usize];
-        let right = &inner_body[(l.end() as isize + offset) as usize..];
-        let text = l.as_str();
-
-        let link = link(text);
-        offset += link.clone().len() as isize - l.as_str().len() as isize;
+/// Escapes text for HTML
+fn escape(text: &str) -> String {
+    MarkupDisplay::new_unsafe(text, Html).to_string()
+}
 
-        inner_body = format!("{}{}{}", left, link, right);
-    });
+/// Formats a post's body as HTML, linking URLs and mentions. Bodies come from upstream, so
+/// everything else is escaped
+pub(crate) fn body(body: &str, base: &Base) -> Result<String, Error> {
+    let at_pat = Regex::new(r"(@[^,?!+ _(){}]*)")?;
+    let mut inner_body = String::new();
 
-    offset = 0;
+    for span in LinkFinder::new().spans(body) {
+        if span.kind().is_some() {
+            inner_body.push_str(&link(span.as_str()));
+            continue;
+        }
 
-    let at_pat = Regex::new(r"(@[^,?!+ _(){}]*)")?;
-    at_pat
-        .captures_iter(inner_body.clone().as_str())
-        .for_each(|c| {
-            c.iter().skip(1).for_each(|m| {
-                if let Some(matched) = m {
-                    let left = &inner_body[..(matched.start() as isize + offset) as usize];
-                    let right = &inner_body[(matched.end() as isize + offset) as usize..];
-                    let text = matched.as_str();
+        let text = span.as_str();
+        let mut cursor = 0;
 
-                    let link = format!("<a href=\"{}/{}\">{}</a>", base.url, text, text);
-                    offset += link.clone().len() as isize - text.len() as isize;
+        for matched in at_pat.find_iter(text) {
+            let mention = escape(matched.as_str());
+            inner_body.push_str(&escape(&text[cursor..matched.start()]));
+            inner_body.push_str(&format!(
+                "<a href=\"{}/{}\">{}</a>",
+                base.url, mention, mention
+            ));
+            cursor = matched.end();
+        }
 
-                    inner_body = format!("{}{}{}", left, link, right);
-                }
-            });
-        });
+        inner_body.push_str(&escape(&text[cursor..]));
+    }
 
     Ok(inner_body)
 }
@@ -1,4 +1,4 @@
-mod formatters;
+pub(crate) mod formatters;
 pub mod routes;
 pub mod templates;
 
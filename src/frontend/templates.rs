@@ -7,7 +7,10 @@ use spools::{Media, MediaKind, Post, Subpost, User};
 
 use crate::{common::error::TimerError, config::Settings, Error, REVISION};
 
-use super::formatters::{body, link, number};
+use super::formatters::{body, link, number, thumbnail};
+
+/// Size for profile pictures shown next to posts, in pixels
+const AVATAR_SIZE: u32 = 96;
 
 #[derive(Debug, PartialEq)]
 enum MediaClosure {
@@ -63,6 +66,7 @@ impl<'a> MediaRender for Media {
 struct FormattedSubpost<'a> {
     input: Subpost,
     code: Option<&'a str>,
+    pfp: &'a str,
     body: &'a str,
     date: &'a str,
     likes: &'a str,
@@ -108,10 +112,12 @@ impl SubpostRender for Subpost {
             .collect::<Result<Vec<String>, Error>>();
 
         let body = body(&self.body, base)?;
+        let pfp = thumbnail(&self.author.pfp, AVATAR_SIZE, base);
 
         let template = FormattedSubpost {
             input: self.clone(),
             code,
+            pfp: &pfp,
             date: date.as_str(),
             body: &body,
             likes: &likes,
@@ -160,6 +166,8 @@ pub struct Base {
     rev: &'static str,
     rss: bool,
     pub(super) url: String,
+    /// Whether proxied images can be resized, which is only worth it if variants are cached
+    pub(super) thumbnails: bool,
     time: Option<u128>,
 }
 
@@ -172,10 +180,19 @@ impl Base {
             rev: &REVISION,
             rss: config.endpoint.rss,
             url: config.server.base_url,
+            thumbnails: config.proxy.cache.is_some_and(|cache| cache.enabled),
             time: None,
         })
     }
 
+    /// Copies the base, pointing links somewhere else
+    pub fn linking_to(&self, url: &str) -> Base {
+        Base {
+            url: url.to_string(),
+            ..self.clone()
+        }
+    }
+
     /// Fetches the current time for use in the time function
     fn now() -> Result<u128, SystemTimeError> {
         let start = SystemTime::now();
@@ -7,12 +7,13 @@ mod rss;
 #[cfg(test)]
 mod test;
 
-use crate::common::config::{Settings, Tls};
+use crate::common::config::{Limit, Limits, Settings, Tls};
 use anyhow::Result;
+use api::auth::{self, Client, Tokens};
 use axum::{
     body::Body,
     extract::{ConnectInfo, Request, State},
-    http::StatusCode,
+    http::{HeaderName, HeaderValue, StatusCode},
     middleware::{self, Next},
     response::IntoResponse,
     RequestPartsExt, Router,
@@ -20,14 +21,15 @@ use axum::{
 use axum_server::tls_rustls::RustlsConfig;
 use common::config;
 use common::error::Error;
-use common::req;
+use common::limiter::{Charge, Limiter};
+use common::req::{self, Inflight};
 use frontend::Base;
 use git_version::git_version;
 use lazy_static::lazy_static;
-use proxy::Keystore;
+use proxy::{keystore, Keystore, MediaCache, Modes};
 use std::{
     fs::File,
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
     process::id,
     sync::{Arc, Mutex},
     time::{Duration, SystemTime, UNIX_EPOCH},
@@ -35,12 +37,17 @@ use std::{
 use tracing::{info, instrument, warn};
 use tracing_log::LogTracer;
 use tracing_subscriber::{filter::LevelFilter, fmt::Layer, prelude::*, EnvFilter, Registry};
+use uuid::Uuid;
 
 #[derive(Clone, Debug)]
 pub struct ShoelaceData {
-    pub store: Keystore,
+    pub store: Arc<dyn Keystore>,
+    pub media: Option<MediaCache>,
     pub base: Base,
     pub config: Settings,
+    pub tokens: Arc<Tokens>,
+    pub clients: Limiter,
+    pub inflight: Inflight,
 }
 
 lazy_static! {
@@ -51,8 +58,39 @@ lazy_static! {
     .trim_end_matches(".0");
 }
 
+tokio::task_local! {
+    /// Identifier for the request being handled
+    pub static REQUEST_ID: String;
+}
+
+/// Header carrying a request's identifier
+static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Header carrying the client IPs seen by reverse proxies
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
 include!(concat!(env!("OUT_DIR"), "/generated.rs"));
 
+/// Picks an identifier for a request, reusing the one given by a reverse proxy if it's sane
+fn request_id(request: &Request) -> String {
+    match request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(id)
+            if !id.is_empty()
+                && id.len() <= 64
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+        {
+            id.to_string()
+        }
+        _ => Uuid::new_v4().simple().to_string(),
+    }
+}
+
 /// Issues a log entry for a request
 #[instrument(name = "web", skip(state, request, next))]
 async fn logger<'a>(
@@ -84,8 +122,13 @@ async fn logger<'a>(
 
     let rebuilt_req = Request::from_parts(parts, body);
     let uri = rebuilt_req.uri().clone();
+    let id = request_id(&rebuilt_req);
 
-    let response = next.run(rebuilt_req).await;
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(rebuilt_req)).await;
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(X_REQUEST_ID.clone(), value);
+    }
 
     let status = response.status();
     let status_chunk = if status == StatusCode::OK {
@@ -108,13 +151,20 @@ async fn logger<'a>(
         String::new()
     };
 
+    let client = match response.extensions().get::<Client>() {
+        Some(client) => format!(" ({})", client.name),
+        None => String::new(),
+    };
+
     let message = format!(
-        "{} {} {}{}{}",
+        "{} {} {}{}{} [{}]{}",
         status_chunk,
         status.as_u16(),
         uri,
         ip,
-        duration
+        duration,
+        id,
+        client
     );
 
     if status.as_u16() < 500 {
@@ -126,6 +176,96 @@ async fn logger<'a>(
     response
 }
 
+/// Sets of routes, rate limited separately
+#[derive(Debug, Clone, Copy)]
+enum Routes {
+    Frontend,
+    Api,
+    Rss,
+    Proxy,
+}
+
+impl Routes {
+    /// Finds the set of routes a path belongs to. Static assets aren't limited
+    fn of(path: &str) -> Option<Self> {
+        match path.trim_start_matches('/').split('/').next() {
+            Some("static") => None,
+            Some("api") => Some(Routes::Api),
+            Some("proxy") => Some(Routes::Proxy),
+            Some("rss" | "atom" | "feed" | "opml") => Some(Routes::Rss),
+            _ => Some(Routes::Frontend),
+        }
+    }
+
+    /// Fetches the configured limit for this set of routes
+    fn limit<'a>(&self, config: &'a Limits) -> &'a Limit {
+        match self {
+            Routes::Frontend => &config.frontend,
+            Routes::Api => &config.api,
+            Routes::Rss => &config.rss,
+            Routes::Proxy => &config.proxy,
+        }
+    }
+}
+
+/// Finds a client's IP, taking it from the last X-Forwarded-For entry if trusted
+fn client_ip(
+    request: &Request,
+    connection: Option<ConnectInfo<SocketAddr>>,
+    trust_forwarded: bool,
+) -> Option<IpAddr> {
+    let forwarded = request
+        .headers()
+        .get(&X_FORWARDED_FOR)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse().ok());
+
+    match forwarded {
+        Some(ip) if trust_forwarded => Some(ip),
+        _ => connection.map(|ConnectInfo(address)| address.ip()),
+    }
+}
+
+/// Limits how often each client can make requests
+#[instrument(name = "limiter", skip(state, connection, request, next))]
+async fn limiter(
+    State(state): State<Arc<ShoelaceData>>,
+    connection: Option<ConnectInfo<SocketAddr>>,
+    mut request: Request,
+    next: Next,
+) -> axum::response::Response {
+    let config = &state.config.limits;
+    let routes = Routes::of(request.uri().path());
+    let ip = client_ip(&request, connection, config.trust_forwarded);
+
+    if let (Some(routes), Some(ip)) = (routes, ip) {
+        let limit = routes.limit(config);
+
+        if limit.rate > 0 {
+            let charge = Charge {
+                key: format!("{:?}:{}", routes, ip),
+                rate: limit.rate,
+                burst: limit.burst,
+            };
+
+            if let Err(wait) = state.clients.charge(&charge, 1).await {
+                let error = Error::RateLimited(wait.as_secs().max(1));
+
+                return match routes {
+                    Routes::Api => error.into_json(),
+                    Routes::Frontend if state.config.endpoint.frontend => error.into_response(),
+                    _ => error.into_plaintext(),
+                };
+            }
+
+            request.extensions_mut().insert(charge);
+        }
+    }
+
+    next.run(request).await
+}
+
 /// Handles fallback responses as 404
 async fn not_found(State(state): State<Arc<ShoelaceData>>) -> (StatusCode, Body) {
     (
@@ -177,13 +317,38 @@ async fn main() -> Result<()> {
     );
 
     let data = Arc::new(ShoelaceData {
-        store: Keystore::new(config.clone().proxy).await?,
+        store: keystore::new(config.clone().proxy).await?,
+        media: match &config.proxy.cache {
+            Some(cache) if cache.enabled => Some(MediaCache::new(cache).await?),
+            _ => None,
+        },
         base: Base::new()?,
         config: config.clone(),
+        tokens: Arc::new(Tokens::new(&config.api)?),
+        clients: Limiter::default(),
+        inflight: Inflight::default(),
     });
 
     info!("Base URL is set to {}", config.server.base_url);
 
+    if let Modes::Signed = config.proxy.mode {
+        // Fail early, rather than on the first proxied link
+        proxy::signing::check(&config.proxy)?;
+        info!("Media links are signed");
+    }
+
+    if config.cache.enabled
+        && matches!(config.proxy.mode, Modes::Keystore)
+        && config.proxy.ttl > 0
+        && config.proxy.ttl < config.cache.user_ttl.max(config.cache.post_ttl)
+    {
+        warn!("Media links expire before cached responses do. Some cached media may fail to load");
+    }
+
+    if config.limits.enabled() && !config.limits.trust_forwarded {
+        warn!("Rate limits key on connecting IPs. Behind a reverse proxy, enable trust_forwarded");
+    }
+
     if !config.endpoint.frontend {
         warn!("Frontend has been disabled");
     }
@@ -193,10 +358,18 @@ async fn main() -> Result<()> {
     }
 
     let app = Router::new()
-        .nest("/api/", api::attach(config.endpoint.api))
-        .nest("/rss/", rss::attach(config.endpoint.rss))
-        .nest("/proxy/", proxy::attach())
+        .nest(
+            "/api/",
+            api::attach(config.endpoint.api)
+                .layer(middleware::from_fn_with_state(data.clone(), auth::api)),
+        )
+        .merge(rss::attach(config.endpoint.rss))
+        .nest(
+            "/proxy/",
+            proxy::attach().layer(middleware::from_fn_with_state(data.clone(), auth::proxy)),
+        )
         .merge(frontend::attach(config.endpoint.frontend))
+        .layer(middleware::from_fn_with_state(data.clone(), limiter))
         .layer(middleware::from_fn_with_state(data.clone(), logger))
         .fallback(not_found)
         .with_state(data);
@@ -231,7 +404,7 @@ async fn main() -> Result<()> {
             format!("{}:{}", config.server.listen, config.server.port).parse()?,
             tls_config,
         )
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await?
     };
 
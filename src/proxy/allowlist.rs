@@ -0,0 +1,57 @@
+use crate::proxy::Error;
+use reqwest::{redirect::Policy, Client, Url};
+
+/// Most redirects followed for a single request, same as reqwest's default
+const MAX_REDIRECTS: usize = 10;
+
+/// Checks whether a host matches a pattern. Patterns starting with "*." match any subdomain
+fn matches(host: &str, pattern: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.') && subdomain.len() > 1),
+        None => host == pattern,
+    }
+}
+
+/// Ensures a URL points to an allowed host. An empty allowlist allows any host
+pub fn check(url: &str, patterns: &[String]) -> Result<(), Error> {
+    if patterns.is_empty() {
+        return Ok(());
+    }
+
+    let parsed = Url::parse(url).map_err(|_| Error::ForbiddenHost(url.to_string()))?;
+
+    let host = match (parsed.scheme(), parsed.host_str()) {
+        ("http" | "https", Some(host)) => host.to_lowercase(),
+        _ => return Err(Error::ForbiddenHost(url.to_string())),
+    };
+
+    if patterns
+        .iter()
+        .any(|pattern| matches(&host, &pattern.to_lowercase()))
+    {
+        Ok(())
+    } else {
+        Err(Error::ForbiddenHost(host))
+    }
+}
+
+/// Builds a client for fetching media. Redirects are checked against the allowlist as well, so an
+/// allowed host can't send the proxy anywhere else
+pub fn client(patterns: &[String]) -> Result<Client, Error> {
+    let patterns = patterns.to_vec();
+
+    Ok(Client::builder()
+        .redirect(Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+
+            match check(attempt.url().as_str(), &patterns) {
+                Ok(()) => attempt.follow(),
+                Err(error) => attempt.error(error),
+            }
+        }))
+        .build()?)
+}
@@ -0,0 +1,499 @@
+use crate::{
+    config,
+    proxy::{conditional, unsatisfiable, ByteRange, Error},
+};
+use axum::{
+    body::{Body, Bytes},
+    http::{
+        header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, LAST_MODIFIED},
+        StatusCode,
+    },
+    response::Response,
+};
+use blake2::{Blake2s256, Digest};
+use futures::{stream, Stream, StreamExt};
+use std::{
+    collections::HashMap,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
+use tokio_util::io::ReaderStream;
+use tracing::{debug, info, warn};
+
+/// Metadata for a file stored within the media cache
+#[derive(Debug, Clone)]
+struct Object {
+    size: u64,
+    created: SystemTime,
+    accessed: SystemTime,
+}
+
+/// Validators stored alongside a cached object, since they can't be recovered from its content
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Reads validators from their file
+    async fn read(path: &Path) -> Self {
+        Self::parse(&fs::read_to_string(path).await.unwrap_or_default())
+    }
+
+    /// Parses serialized validators, stored as one per line. Missing ones are left empty
+    pub fn parse(stored: &str) -> Self {
+        let mut lines = stored
+            .lines()
+            .map(|line| Some(line.to_string()).filter(|line| !line.is_empty()));
+
+        Self {
+            etag: lines.next().flatten(),
+            last_modified: lines.next().flatten(),
+        }
+    }
+
+    /// Serializes validators, for their file or the keystore
+    pub fn write(&self) -> String {
+        format!(
+            "{}\n{}\n",
+            self.etag.as_deref().unwrap_or_default(),
+            self.last_modified.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+/// Disk-backed cache for proxied media, keyed by its hash
+#[derive(Debug, Clone)]
+pub struct MediaCache {
+    directory: PathBuf,
+    max_size: u64,
+    ttl: Option<Duration>,
+    index: Arc<Mutex<HashMap<String, Object>>>,
+    counter: Arc<AtomicU64>,
+}
+
+/// File being written into the cache, removed unless it makes it in
+struct Part {
+    path: PathBuf,
+    file: File,
+}
+
+impl Drop for Part {
+    fn drop(&mut self) {
+        // Committed parts have been moved already, so this only catches abandoned ones
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Media object opened from the cache
+pub struct Cached {
+    file: File,
+    size: u64,
+    mime: String,
+    validators: Validators,
+}
+
+impl MediaCache {
+    /// Builds a new media cache, indexing any files left from previous runs
+    #[tracing::instrument(name = "init", skip(config))]
+    pub async fn new(config: &config::MediaCache) -> Result<Self, std::io::Error> {
+        let directory = PathBuf::from(&config.directory);
+        fs::create_dir_all(&directory).await?;
+
+        let mut index = HashMap::new();
+        let mut validators = Vec::new();
+        let mut entries = fs::read_dir(&directory).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let metadata = entry.metadata().await?;
+
+            if !metadata.is_file() {
+                continue;
+            }
+
+            // Unfinished downloads can't be trusted
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "part")
+            {
+                fs::remove_file(&path).await?;
+                continue;
+            }
+
+            // Validators are read along with their object, once it's known to still exist
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "meta")
+            {
+                validators.push(path);
+                continue;
+            }
+
+            let modified = metadata.modified()?;
+
+            index.insert(
+                entry.file_name().to_string_lossy().to_string(),
+                Object {
+                    size: metadata.len(),
+                    created: modified,
+                    accessed: modified,
+                },
+            );
+        }
+
+        for path in validators {
+            let orphaned = path
+                .file_stem()
+                .is_none_or(|hash| !index.contains_key(hash.to_string_lossy().as_ref()));
+
+            if orphaned {
+                fs::remove_file(&path).await?;
+            }
+        }
+
+        info!(
+            "Caching media at {} ({} objects)",
+            directory.display(),
+            index.len()
+        );
+
+        let cache = Self {
+            directory,
+            max_size: config.max_size * 1024 * 1024,
+            ttl: if config.ttl > 0 {
+                Some(Duration::from_secs(config.ttl))
+            } else {
+                None
+            },
+            index: Arc::new(Mutex::new(index)),
+            counter: Arc::new(AtomicU64::new(0)),
+        };
+
+        cache.evict().await;
+
+        Ok(cache)
+    }
+
+    /// Checks whether an object has outlived the cache's TTL
+    fn expired(&self, object: &Object) -> bool {
+        match (self.ttl, object.created.elapsed()) {
+            (Some(ttl), Ok(age)) => age > ttl,
+            _ => false,
+        }
+    }
+
+    /// Path of the file holding an object's validators
+    fn meta(&self, hash: &str) -> PathBuf {
+        self.directory.join(format!("{}.meta", hash))
+    }
+
+    /// Deletes an object's file, along with its validators
+    async fn remove(&self, hash: &str) -> Result<(), std::io::Error> {
+        let _ = fs::remove_file(self.meta(hash)).await;
+        fs::remove_file(self.directory.join(hash)).await
+    }
+
+    /// Opens an object from the cache, if present and fresh
+    pub async fn open(&self, hash: &str) -> Option<Cached> {
+        let size = {
+            let mut lock = self.index.lock().await;
+            let object = lock.get_mut(hash)?;
+
+            if self.expired(object) {
+                lock.remove(hash);
+                let _ = self.remove(hash).await;
+                return None;
+            }
+
+            object.accessed = SystemTime::now();
+            object.size
+        };
+
+        let mut file = match File::open(self.directory.join(hash)).await {
+            Ok(file) => file,
+            Err(error) => {
+                warn!("Dropping unreadable cached media {}: {}", hash, error);
+                self.index.lock().await.remove(hash);
+                let _ = self.remove(hash).await;
+                return None;
+            }
+        };
+
+        // Media types aren't stored, so they're sniffed again from the file's header
+        let mut head = [0; 8192];
+        let read = file.read(&mut head).await.ok()?;
+        let mime = infer::get(&head[..read])?.to_string();
+
+        debug!("Cache hit for media {}", hash);
+
+        Some(Cached {
+            file,
+            size,
+            mime,
+            validators: Validators::read(&self.meta(hash)).await,
+        })
+    }
+
+    /// Generates a unique path for an unfinished download
+    fn part(&self, hash: &str) -> PathBuf {
+        self.directory.join(format!(
+            "{}.{}.part",
+            hash,
+            self.counter.fetch_add(1, Ordering::Relaxed)
+        ))
+    }
+
+    /// Stores an object which is already held in memory
+    pub async fn store(&self, hash: &str, media: &[u8], validators: Validators) {
+        let part = self.part(hash);
+
+        let mut file = match File::create(&part).await {
+            Ok(file) => file,
+            Err(error) => {
+                warn!("Couldn't store media {} in cache: {}", hash, error);
+                return;
+            }
+        };
+
+        if let Err(error) = file.write_all(media).await {
+            warn!("Couldn't write media {} to cache: {}", hash, error);
+            let _ = fs::remove_file(&part).await;
+            return;
+        }
+
+        self.commit(hash, &part, &mut file, media.len() as u64, validators)
+            .await;
+    }
+
+    /// Stores a media stream on disk while it's being served. Objects without an entity tag get one
+    /// from their content once they're complete
+    pub async fn tee<S, E>(
+        &self,
+        hash: &str,
+        media: S,
+        validators: Validators,
+    ) -> Result<impl Stream<Item = Result<Bytes, E>>, std::io::Error>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    {
+        let path = self.part(hash);
+        let file = File::create(&path).await?;
+
+        let cache = self.clone();
+        let hash = hash.to_string();
+        // Parts are dropped alongside the stream, so clients going away don't leave them behind
+        let hasher = validators.etag.is_none().then(Blake2s256::new);
+        let state = Some((
+            Box::pin(media),
+            Some(Part { path, file }),
+            (validators, hasher),
+            0u64,
+        ));
+
+        Ok(stream::unfold(state, move |state| {
+            let cache = cache.clone();
+            let hash = hash.clone();
+
+            async move {
+                let (mut media, mut part, (mut validators, mut hasher), written) = state?;
+
+                match media.next().await {
+                    Some(Ok(chunk)) => {
+                        if let Some(handle) = part.as_mut() {
+                            if let Err(error) = handle.file.write_all(&chunk).await {
+                                warn!("Couldn't write media {} to cache: {}", hash, error);
+                                part = None;
+                            }
+                        }
+
+                        if let Some(hasher) = hasher.as_mut() {
+                            hasher.update(&chunk);
+                        }
+
+                        let written = written + chunk.len() as u64;
+                        Some((
+                            Ok(chunk),
+                            Some((media, part, (validators, hasher), written)),
+                        ))
+                    }
+                    Some(Err(error)) => Some((Err(error), None)),
+                    None => {
+                        if let Some(mut part) = part {
+                            validators.etag = validators.etag.or(hasher.map(conditional::hashed));
+
+                            cache
+                                .commit(&hash, &part.path, &mut part.file, written, validators)
+                                .await;
+                        }
+
+                        None
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Moves a finished download into the cache, along with its validators
+    async fn commit(
+        &self,
+        hash: &str,
+        part: &Path,
+        file: &mut File,
+        size: u64,
+        validators: Validators,
+    ) {
+        let result = match file.flush().await {
+            Ok(()) => match fs::write(self.meta(hash), validators.write()).await {
+                Ok(()) => fs::rename(part, self.directory.join(hash)).await,
+                Err(error) => Err(error),
+            },
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = result {
+            warn!("Couldn't store media {} in cache: {}", hash, error);
+            let _ = fs::remove_file(part).await;
+            let _ = fs::remove_file(self.meta(hash)).await;
+            return;
+        }
+
+        let now = SystemTime::now();
+
+        self.index.lock().await.insert(
+            hash.to_string(),
+            Object {
+                size,
+                created: now,
+                accessed: now,
+            },
+        );
+
+        debug!("Stored media {} in cache ({} bytes)", hash, size);
+
+        self.evict().await;
+    }
+
+    /// Drops expired objects, then the least recently used ones until the cache fits its size cap
+    async fn evict(&self) {
+        let mut lock = self.index.lock().await;
+
+        let mut evicted: Vec<String> = lock
+            .iter()
+            .filter(|(_, object)| self.expired(object))
+            .map(|(hash, _)| hash.clone())
+            .collect();
+
+        evicted.iter().for_each(|hash| {
+            lock.remove(hash);
+        });
+
+        let mut total: u64 = lock.values().map(|object| object.size).sum();
+
+        while total > self.max_size {
+            let oldest = lock
+                .iter()
+                .min_by_key(|(_, object)| object.accessed)
+                .map(|(hash, object)| (hash.clone(), object.size));
+
+            match oldest {
+                Some((hash, size)) => {
+                    lock.remove(&hash);
+                    evicted.push(hash);
+                    total -= size;
+                }
+                None => break,
+            }
+        }
+
+        drop(lock);
+
+        for hash in evicted {
+            if let Err(error) = self.remove(&hash).await {
+                warn!("Couldn't evict media {} from cache: {}", hash, error);
+            }
+        }
+    }
+}
+
+impl Cached {
+    /// Checks whether the cached object is an image
+    pub fn is_image(&self) -> bool {
+        self.mime.starts_with("image/")
+    }
+
+    /// Object's MIME type
+    pub fn mime(&self) -> &str {
+        &self.mime
+    }
+
+    /// Object's size, in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Reads the whole object into memory
+    pub async fn bytes(mut self) -> Result<Vec<u8>, std::io::Error> {
+        let mut media = Vec::with_capacity(self.size as usize);
+
+        self.file.seek(SeekFrom::Start(0)).await?;
+        self.file.read_to_end(&mut media).await?;
+
+        Ok(media)
+    }
+
+    /// Object's validators, as they were when it was stored
+    pub fn validators(&self) -> &Validators {
+        &self.validators
+    }
+
+    /// Serves a cached object, optionally limited to a byte range
+    pub async fn respond(mut self, range: Option<ByteRange>) -> Result<Response, Error> {
+        let mut response = Response::builder()
+            .header(CONTENT_TYPE, self.mime)
+            .header(ACCEPT_RANGES, "bytes");
+
+        if let Some(etag) = self.validators.etag {
+            response = response.header(ETAG, etag);
+        }
+
+        if let Some(last_modified) = self.validators.last_modified {
+            response = response.header(LAST_MODIFIED, last_modified);
+        }
+
+        let Some(range) = range else {
+            self.file.seek(SeekFrom::Start(0)).await?;
+
+            return Ok(response
+                .header(CONTENT_LENGTH, self.size)
+                .body(Body::from_stream(ReaderStream::new(self.file)))?);
+        };
+
+        let Some((start, end)) = range.resolve(self.size) else {
+            return unsatisfiable(self.size);
+        };
+
+        self.file.seek(SeekFrom::Start(start)).await?;
+        let length = end - start + 1;
+
+        Ok(response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, self.size),
+            )
+            .header(CONTENT_LENGTH, length)
+            .body(Body::from_stream(ReaderStream::new(self.file.take(length))))?)
+    }
+}
@@ -0,0 +1,120 @@
+use axum::{
+    body::Body,
+    http::{
+        header::{CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    response::Response,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use blake2::{Blake2s256, Digest};
+use std::time::SystemTime;
+
+/// Proxied media never changes under the same link, so it can be cached for as long as browsers allow
+pub const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Builds a strong entity tag for an object
+pub fn etag(key: &str) -> String {
+    format!("\"{}\"", key)
+}
+
+/// Builds a strong entity tag from a hash of an object's content
+pub fn hashed(hasher: Blake2s256) -> String {
+    etag(&URL_SAFE_NO_PAD.encode(hasher.finalize()))
+}
+
+/// Formats a timestamp as an HTTP date
+pub fn http_date(time: SystemTime) -> String {
+    httpdate::fmt_http_date(time)
+}
+
+/// Checks whether the client's If-None-Match header matches an entity tag. Since `*` matches any
+/// tag, this is only meaningful once the object is known to exist
+pub fn none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Checks whether an object was modified after the client's If-Modified-Since header
+pub fn modified_since(headers: &HeaderMap, last_modified: &HeaderValue) -> bool {
+    let since = headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok());
+
+    let modified = last_modified
+        .to_str()
+        .ok()
+        .and_then(|value| httpdate::parse_http_date(value).ok());
+
+    match (since, modified) {
+        (Some(since), Some(modified)) => modified > since,
+        _ => true,
+    }
+}
+
+/// Checks whether the client's copy of an object is still fresh. If-None-Match takes precedence
+/// over If-Modified-Since, so objects without an entity tag are never fresh if it's sent
+pub fn fresh(headers: &HeaderMap, etag: Option<&str>, last_modified: Option<&HeaderValue>) -> bool {
+    if headers.contains_key(IF_NONE_MATCH) {
+        etag.is_some_and(|etag| none_match(headers, etag))
+    } else {
+        last_modified.is_some_and(|date| !modified_since(headers, date))
+    }
+}
+
+/// Builds a response for objects the client already has
+pub fn not_modified(
+    etag: Option<&str>,
+    last_modified: Option<HeaderValue>,
+    cache_control: &str,
+) -> Response {
+    let mut response = Response::builder()
+        .status(StatusCode::NOT_MODIFIED)
+        .header(CACHE_CONTROL, cache_control);
+
+    if let Some(etag) = etag.and_then(|etag| HeaderValue::from_str(etag).ok()) {
+        response = response.header(ETAG, etag);
+    }
+
+    if let Some(last_modified) = last_modified {
+        response = response.header(LAST_MODIFIED, last_modified);
+    }
+
+    response.body(Body::empty()).unwrap_or_default()
+}
+
+/// Adds caching headers to a response, turning it into a 304 if the client's copy is still fresh.
+/// Validators are taken from the response itself, which is only built once the object exists
+pub fn finish(mut response: Response, headers: &HeaderMap, cache_control: &str) -> Response {
+    if !response.status().is_success() {
+        return response;
+    }
+
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    let last_modified = response.headers().get(LAST_MODIFIED).cloned();
+
+    // Conditions are evaluated before ranges, so partial responses can be skipped as well
+    if matches!(
+        response.status(),
+        StatusCode::OK | StatusCode::PARTIAL_CONTENT
+    ) && fresh(headers, etag.as_deref(), last_modified.as_ref())
+    {
+        return not_modified(etag.as_deref(), last_modified, cache_control);
+    }
+
+    if let Ok(value) = HeaderValue::from_str(cache_control) {
+        response.headers_mut().insert(CACHE_CONTROL, value);
+    }
+
+    response
+}
@@ -1,10 +1,7 @@
-use axum::{
-    http::StatusCode,
-    response::{IntoResponse, Response},
-};
+use axum::response::{IntoResponse, Response};
 use thiserror::Error;
 
-use crate::proxy::keystore::Backends;
+use crate::{common::error::Code, proxy::keystore::Backends};
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -20,33 +17,63 @@ pub enum Error {
     Keystore(#[from] KeystoreError),
     #[error("Web server error: {0}")]
     Web(#[from] axum::http::Error),
+    #[error("Media cache error: {0}")]
+    Cache(#[from] std::io::Error),
+    #[error("Signed links require a secret")]
+    MissingSecret,
+    #[error("Signed links require a secret other than the sample one")]
+    PlaceholderSecret,
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Host isn't allowed: {0}")]
+    ForbiddenHost(String),
+    #[error("Couldn't transform image: {0}")]
+    Transform(#[from] image::ImageError),
+    #[error("Background task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
 }
 
 #[derive(Error, Debug)]
 pub enum KeystoreError {
     #[error("{0}")]
     RedisError(#[from] redis::RedisError),
+    #[error("{0}")]
+    SqliteError(#[from] rusqlite::Error),
+    #[error("background task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+    #[error("connection lock was poisoned")]
+    Poisoned,
     #[error("invalid config for {0}")]
     InvalidConfig(Backends),
 }
 
-impl IntoResponse for Error {
-    fn into_response(self) -> Response {
-        let status: StatusCode = match self {
-            Self::ObjectNotFound => StatusCode::NOT_FOUND,
-            Self::Endpoint(ref val) => {
-                if let Some(status) = val.status() {
-                    match status {
-                        reqwest::StatusCode::NOT_FOUND => StatusCode::NOT_FOUND,
-                        _ => StatusCode::BAD_GATEWAY,
-                    }
-                } else {
-                    StatusCode::BAD_GATEWAY
-                }
+impl Error {
+    /// Machine-readable code for the error
+    pub fn code(&self) -> Code {
+        match self {
+            Self::ObjectNotFound => Code::NotFound,
+            Self::InvalidSignature | Self::ForbiddenHost(_) => Code::Forbidden,
+            Self::Endpoint(error) if error.is_timeout() => Code::UpstreamTimeout,
+            // Redirects to hosts outside the allowlist are refused like any other link to them
+            Self::Endpoint(error)
+                if std::error::Error::source(error).is_some_and(|source| source.is::<Error>()) =>
+            {
+                Code::Forbidden
             }
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        };
+            Self::Endpoint(error) => match error.status() {
+                Some(reqwest::StatusCode::NOT_FOUND) => Code::NotFound,
+                Some(reqwest::StatusCode::TOO_MANY_REQUESTS) => Code::UpstreamRateLimited,
+                _ => Code::UpstreamUnavailable,
+            },
+            Self::UnidentifiableMime => Code::UpstreamUnavailable,
+            Self::NoProxy | Self::Keystore(_) => Code::ServiceUnavailable,
+            _ => Code::ProxyFailed,
+        }
+    }
+}
 
-        (status, self.to_string()).into_response()
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        (self.code().status(), self.to_string()).into_response()
     }
 }
@@ -0,0 +1,107 @@
+use super::{Keystore, SWEEP_INTERVAL};
+use crate::proxy::KeystoreError;
+use async_trait::async_trait;
+use lru::LruCache;
+use std::{
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{sync::Mutex, time};
+use tracing::debug;
+
+/// Value stored within the internal keystore
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub value: String,
+    pub expiry: Option<Instant>,
+}
+
+impl Entry {
+    /// Builds a new entry, which optionally expires after a given duration
+    pub fn new(value: &str, ttl: Option<Duration>) -> Self {
+        Self {
+            value: value.to_string(),
+            expiry: ttl.map(|ttl| Instant::now() + ttl),
+        }
+    }
+
+    /// Checks whether the entry has outlived its expiry date
+    pub fn expired(&self) -> bool {
+        matches!(self.expiry, Some(expiry) if expiry <= Instant::now())
+    }
+}
+
+/// Keystore kept in memory, which is essentially just an LRU map
+#[derive(Debug, Clone)]
+pub struct Internal(Arc<Mutex<LruCache<String, Entry>>>);
+
+impl Internal {
+    /// Builds an internal keystore, optionally bounded to a maximum amount of entries
+    pub fn new(max_entries: usize) -> Self {
+        let map = match NonZeroUsize::new(max_entries) {
+            Some(capacity) => LruCache::new(capacity),
+            None => LruCache::unbounded(),
+        };
+
+        Self(Arc::new(Mutex::new(map)))
+    }
+
+    /// Spawns a background task which periodically drops expired entries
+    pub fn sweep(self) -> Self {
+        let store = self.0.clone();
+
+        tokio::spawn(async move {
+            let mut interval = time::interval(SWEEP_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let mut lock = store.lock().await;
+                let expired: Vec<String> = lock
+                    .iter()
+                    .filter(|(_, entry)| entry.expired())
+                    .map(|(key, _)| key.clone())
+                    .collect();
+
+                expired.iter().for_each(|key| {
+                    lock.pop(key);
+                });
+
+                if !expired.is_empty() {
+                    debug!("Swept {} expired keystore entries", expired.len());
+                }
+            }
+        });
+
+        self
+    }
+}
+
+#[async_trait]
+impl Keystore for Internal {
+    async fn get(&self, key: &str) -> Result<Option<String>, KeystoreError> {
+        let mut lock = self.0.lock().await;
+
+        match lock.get(key) {
+            Some(entry) if entry.expired() => {
+                lock.pop(key);
+                Ok(None)
+            }
+            Some(entry) => Ok(Some(entry.value.clone())),
+            None => Ok(None),
+        }
+    }
+
+    async fn set(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Option<Duration>,
+    ) -> Result<(), KeystoreError> {
+        let mut lock = self.0.lock().await;
+        lock.put(key.to_string(), Entry::new(value, ttl));
+
+        Ok(())
+    }
+}
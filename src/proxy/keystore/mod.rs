@@ -0,0 +1,115 @@
+mod internal;
+mod redis;
+mod sqlite;
+
+pub use internal::Internal;
+pub use redis::Redis;
+pub use sqlite::Sqlite;
+
+use crate::{config::Proxy, proxy::KeystoreError};
+use async_trait::async_trait;
+use core::fmt;
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+use tracing::{info, warn};
+
+/// How often expired entries get swept from backends which don't expire them on their own
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Storage for proxied media hashes and cached responses
+#[async_trait]
+pub trait Keystore: fmt::Debug + Send + Sync {
+    /// Fetches a value from the keystore, if it exists and hasn't expired
+    async fn get(&self, key: &str) -> Result<Option<String>, KeystoreError>;
+
+    /// Stores a value in the keystore, optionally expiring after a given duration
+    async fn set(&self, key: &str, value: &str, ttl: Option<Duration>)
+        -> Result<(), KeystoreError>;
+
+    /// Whether the keystore stores anything at all
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    /// Describes where the keystore's data is being kept, if applicable
+    fn location(&self) -> Option<String> {
+        None
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub enum Backends {
+    None,
+    Internal,
+    Redis,
+    Sqlite,
+}
+
+/// Keystore which doesn't store anything, disabling the proxy
+#[derive(Debug, Clone)]
+pub struct Disabled;
+
+#[async_trait]
+impl Keystore for Disabled {
+    async fn get(&self, _key: &str) -> Result<Option<String>, KeystoreError> {
+        Ok(None)
+    }
+
+    async fn set(
+        &self,
+        _key: &str,
+        _value: &str,
+        _ttl: Option<Duration>,
+    ) -> Result<(), KeystoreError> {
+        Ok(())
+    }
+
+    fn enabled(&self) -> bool {
+        false
+    }
+}
+
+/// Builds a new keystore for the configured backend
+#[tracing::instrument(name = "init", skip(config))]
+pub async fn new(config: Proxy) -> Result<Arc<dyn Keystore>, KeystoreError> {
+    let backend: Arc<dyn Keystore> = match config.backend {
+        Backends::Redis => match config.redis {
+            Some(redis) => Arc::new(Redis::new(&redis.uri).await?),
+            None => return Err(KeystoreError::InvalidConfig(config.backend)),
+        },
+        Backends::Sqlite => match config.sqlite {
+            Some(sqlite) => Arc::new(Sqlite::new(&sqlite.path).await?),
+            None => return Err(KeystoreError::InvalidConfig(config.backend)),
+        },
+        Backends::Internal => Arc::new(Internal::new(config.max_entries).sweep()),
+        Backends::None => Arc::new(Disabled),
+    };
+
+    if backend.enabled() {
+        info!(
+            "Connected to {} keystore {}",
+            &config.backend,
+            match backend.location() {
+                Some(location) => format!("at {}", location),
+                None => String::new(),
+            }
+        );
+    } else {
+        warn!("No keystore backend. Proxy has been disabled")
+    }
+
+    Ok(backend)
+}
+
+impl fmt::Display for Backends {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::fmt::Result {
+        let out = match self {
+            Backends::Redis => "Redis",
+            Backends::Sqlite => "SQLite",
+            Backends::Internal => "Internal",
+            Backends::None => "None",
+        };
+
+        write!(f, "{}", out)
+    }
+}
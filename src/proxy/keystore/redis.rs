@@ -0,0 +1,66 @@
+use super::Keystore;
+use crate::proxy::KeystoreError;
+use async_trait::async_trait;
+use redis::{aio::MultiplexedConnection, ConnectionAddr};
+use std::time::Duration;
+
+/// Keystore backed by a Redis server
+#[derive(Debug, Clone)]
+pub struct Redis {
+    connection: MultiplexedConnection,
+    address: ConnectionAddr,
+}
+
+impl Redis {
+    /// Connects to a Redis server
+    pub async fn new(uri: &str) -> Result<Self, KeystoreError> {
+        let client = redis::Client::open(uri)?;
+        let address = client.get_connection_info().addr.clone();
+
+        Ok(Self {
+            connection: client.get_multiplexed_async_connection().await?,
+            address,
+        })
+    }
+}
+
+#[async_trait]
+impl Keystore for Redis {
+    async fn get(&self, key: &str) -> Result<Option<String>, KeystoreError> {
+        let mut con = self.connection.to_owned();
+
+        Ok(redis::cmd("GET").arg(key).query_async(&mut con).await?)
+    }
+
+    async fn set(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Option<Duration>,
+    ) -> Result<(), KeystoreError> {
+        let mut con = self.connection.to_owned();
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(key).arg(value);
+
+        if let Some(ttl) = ttl {
+            cmd.arg("EX").arg(ttl.as_secs().max(1));
+        }
+
+        let _: () = cmd.query_async(&mut con).await?;
+
+        Ok(())
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(match &self.address {
+            ConnectionAddr::Tcp(host, port) => format!("redis://{}:{}", host, port),
+            ConnectionAddr::TcpTls {
+                host,
+                port,
+                insecure: _,
+                tls_params: _,
+            } => format!("redis://{}:{} (TLS)", host, port),
+            ConnectionAddr::Unix(path) => format!("redis+unix://{}", path.display()),
+        })
+    }
+}
@@ -0,0 +1,143 @@
+use super::{Keystore, SWEEP_INTERVAL};
+use crate::proxy::KeystoreError;
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{task, time};
+use tracing::{debug, warn};
+
+/// Keystore backed by an embedded SQLite database, which persists across restarts
+#[derive(Debug, Clone)]
+pub struct Sqlite {
+    connection: Arc<Mutex<Connection>>,
+    path: String,
+}
+
+/// Fetches the current UNIX timestamp, which expiry dates are stored as
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+impl Sqlite {
+    /// Opens a database, creating it if it doesn't exist yet
+    pub async fn new(path: &str) -> Result<Self, KeystoreError> {
+        let owned_path = path.to_string();
+
+        let connection = task::spawn_blocking(move || {
+            let connection = Connection::open(owned_path)?;
+
+            connection.execute_batch(
+                "PRAGMA journal_mode = WAL;
+                CREATE TABLE IF NOT EXISTS keystore (
+                    key TEXT PRIMARY KEY,
+                    value TEXT NOT NULL,
+                    expiry INTEGER
+                );",
+            )?;
+
+            Ok::<Connection, rusqlite::Error>(connection)
+        })
+        .await??;
+
+        let keystore = Self {
+            connection: Arc::new(Mutex::new(connection)),
+            path: path.to_string(),
+        };
+
+        keystore.sweep();
+
+        Ok(keystore)
+    }
+
+    /// Runs a query against the database, without blocking the runtime
+    async fn run<T, F>(&self, query: F) -> Result<T, KeystoreError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+
+        task::spawn_blocking(move || {
+            let lock = connection.lock().map_err(|_| KeystoreError::Poisoned)?;
+            Ok(query(&lock)?)
+        })
+        .await?
+    }
+
+    /// Spawns a background task which periodically drops expired entries
+    fn sweep(&self) {
+        let keystore = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = time::interval(SWEEP_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let swept = keystore
+                    .run(|connection| {
+                        connection.execute(
+                            "DELETE FROM keystore WHERE expiry IS NOT NULL AND expiry <= ?1",
+                            params![now()],
+                        )
+                    })
+                    .await;
+
+                match swept {
+                    Ok(0) => {}
+                    Ok(count) => debug!("Swept {} expired keystore entries", count),
+                    Err(error) => warn!("Couldn't sweep keystore: {}", error),
+                }
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl Keystore for Sqlite {
+    async fn get(&self, key: &str) -> Result<Option<String>, KeystoreError> {
+        let key = key.to_string();
+
+        self.run(move |connection| {
+            connection
+                .query_row(
+                    "SELECT value FROM keystore WHERE key = ?1 AND (expiry IS NULL OR expiry > ?2)",
+                    params![key, now()],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+        .await
+    }
+
+    async fn set(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Option<Duration>,
+    ) -> Result<(), KeystoreError> {
+        let key = key.to_string();
+        let value = value.to_string();
+        let expiry = ttl.map(|ttl| now() + ttl.as_secs() as i64);
+
+        self.run(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO keystore (key, value, expiry) VALUES (?1, ?2, ?3)",
+                params![key, value, expiry],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(self.path.clone())
+    }
+}
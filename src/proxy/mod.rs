@@ -1,108 +1,560 @@
+pub mod allowlist;
+pub mod cache;
+pub mod conditional;
 pub mod error;
 pub mod keystore;
+pub mod range;
+pub mod signing;
+pub mod transform;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+pub use cache::{MediaCache, Validators};
 pub use error::{Error, KeystoreError};
 pub use keystore::{Backends, Keystore};
+pub use range::ByteRange;
+pub use signing::Modes;
+pub use transform::Transform;
 
 use crate::ShoelaceData;
 use axum::{
-    body::Body,
-    extract::{Path, State},
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{
+        header::{
+            ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, LAST_MODIFIED, RANGE,
+        },
+        HeaderMap, HeaderValue, StatusCode,
+    },
     response::Response,
     routing::get,
     Router,
 };
 use base64::{engine::general_purpose::URL_SAFE, Engine as _};
 use blake2::{Blake2s256, Digest};
-use tracing::info;
+use futures::{stream, Stream, StreamExt};
+use tokio::task;
+use tracing::{info, warn};
+
+/// Largest image which can be transformed, since it's held in memory while doing so
+const MAX_TRANSFORM_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Longest time media lookups can take, so they don't hold up whoever requested them
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Attaches the Proxy module to an Axum router
 pub fn attach() -> Router<Arc<ShoelaceData>> {
     Router::new().route("/:id", get(serve))
 }
 
+/// Hashes a media URL
+fn digest(url: &str) -> String {
+    URL_SAFE.encode(Blake2s256::digest(url.as_bytes()))
+}
+
 /// Stores media URLs
 #[tracing::instrument(err(Display), skip(url, data))]
 pub async fn store(url: &str, data: ShoelaceData) -> Result<String, Error> {
-    let hash = Blake2s256::digest(url.as_bytes());
-    let hashstring = URL_SAFE.encode(hash).to_string();
+    let signed = matches!(data.config.proxy.mode, Modes::Signed);
+
+    if !signed && !data.store.enabled() {
+        return Ok(url.to_string());
+    }
+
+    allowlist::check(url, &data.config.proxy.allowed_hosts)?;
+
+    if signed {
+        return Ok(format!(
+            "{}/proxy/{}",
+            data.config.server.base_url,
+            signing::sign(url, &data.config.proxy)?
+        ));
+    }
+
+    let hashstring = digest(url);
     let hash_url = format!(
         "{}/proxy/{}",
         data.config.server.base_url,
         hashstring.clone()
     );
 
-    let result = match &data.store {
-        Keystore::Internal(store) => {
-            let mut lock = store.lock().await;
-            lock.insert(hashstring.clone(), url.to_string());
-            Ok(hash_url)
+    let ttl = match data.config.proxy.ttl {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+
+    data.store.set(&hashstring, url, ttl).await?;
+
+    info!(
+        "Spawned hash {}{}",
+        &hashstring,
+        if data.config.logging.log_cdn {
+            format!(" -> {}", url)
+        } else {
+            String::from("")
         }
-        Keystore::Redis(store) => {
-            let mut con = store.to_owned();
+    );
 
-            redis::cmd("SET")
-                .arg(&[hashstring.clone(), url.to_string()])
-                .query_async(&mut con)
-                .await
-                .map_err(KeystoreError::RedisError)?;
-            Ok(hash_url)
+    Ok(hash_url)
+}
+
+/// Resolves a link generated by the proxy into the location it points to, alongside its hash
+async fn resolve(link: &str, data: &ShoelaceData) -> Result<(String, Option<String>), Error> {
+    let prefix = format!("{}/proxy/", data.config.server.base_url);
+
+    // Links are left untouched when the proxy is disabled
+    let Some(id) = link.strip_prefix(&prefix) else {
+        return Ok((link.to_string(), None));
+    };
+
+    if signing::accepts(id, &data.config.proxy) {
+        let url = signing::verify(id, &data.config.proxy)?;
+        let hash = digest(&url);
+
+        return Ok((url, Some(hash)));
+    }
+
+    let url = data.store.get(id).await?.ok_or(Error::ObjectNotFound)?;
+
+    Ok((url, Some(id.to_string())))
+}
+
+/// Looks up the MIME type and size of media, without downloading it
+#[tracing::instrument(err(Display), skip(link, data))]
+pub async fn probe(
+    link: &str,
+    data: &ShoelaceData,
+) -> Result<(Option<String>, Option<u64>), Error> {
+    let (url, hash) = resolve(link, data).await?;
+
+    if let (Some(cache), Some(hash)) = (&data.media, &hash) {
+        if let Some(cached) = cache.open(hash).await {
+            return Ok((Some(cached.mime().to_string()), Some(cached.size())));
         }
-        Keystore::None => Ok(url.to_string()),
+    }
+
+    // Results are kept for as long as links are, so feeds don't look up the same media every time
+    let key = format!("probe:{}", hash.unwrap_or_else(|| digest(&url)));
+
+    if data.store.enabled() {
+        if let Some(probed) = data.store.get(&key).await? {
+            let (mime, length) = probed.split_once('\n').unwrap_or_default();
+
+            return Ok((
+                (!mime.is_empty()).then(|| mime.to_string()),
+                length.parse().ok(),
+            ));
+        }
+    }
+
+    allowlist::check(&url, &data.config.proxy.allowed_hosts)?;
+
+    let upstream = allowlist::client(&data.config.proxy.allowed_hosts)?
+        .head(url)
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // Bodies are empty on HEAD requests, so the length is taken from the header itself
+    let header = |name| {
+        upstream
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
     };
 
-    if !matches!(&data.store, Keystore::None) {
-        info!(
-            "Spawned hash {}{}",
-            &hashstring,
-            if data.config.logging.log_cdn {
-                format!(" -> {}", url)
-            } else {
-                String::from("")
-            }
+    let (mime, length) = (
+        header(CONTENT_TYPE),
+        header(CONTENT_LENGTH).and_then(|length| length.parse::<u64>().ok()),
+    );
+
+    if data.store.enabled() {
+        let ttl = match data.config.proxy.ttl {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+
+        let probed = format!(
+            "{}\n{}",
+            mime.as_deref().unwrap_or_default(),
+            length.map(|length| length.to_string()).unwrap_or_default()
         );
+
+        data.store.set(&key, &probed, ttl).await?;
     }
 
-    result
+    Ok((mime, length))
+}
+
+/// Builds a response for ranges which can't be served
+pub(crate) fn unsatisfiable(length: u64) -> Result<Response, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+        .header(ACCEPT_RANGES, "bytes")
+        .header(CONTENT_RANGE, format!("bytes */{}", length))
+        .body(Body::empty())?)
+}
+
+/// Serves a resized or converted variant of an image, which keeps its source's Last-Modified
+async fn variant(
+    hash: &str,
+    source: Vec<u8>,
+    source_mime: &str,
+    last_modified: Option<String>,
+    transform: &Transform,
+    data: &ShoelaceData,
+) -> Result<Response, Error> {
+    // Encoding is CPU-bound, so it's kept off the runtime's threads
+    let owned_transform = transform.clone();
+    let transformed = task::spawn_blocking(move || {
+        owned_transform
+            .apply(&source)
+            .map_err(|error| (error, source))
+    })
+    .await?;
+
+    // Images which can't be decoded are still served, just as they are
+    let (media, mime) = match transformed {
+        Ok((media, mime)) => (media, mime),
+        Err((error, source)) => {
+            warn!("Serving {} untransformed: {}", hash, error);
+            (source, source_mime)
+        }
+    };
+
+    let validators = Validators {
+        etag: Some(conditional::hashed(Blake2s256::new_with_prefix(&media))),
+        last_modified,
+    };
+
+    match &data.media {
+        Some(cache) => {
+            cache
+                .store(&transform.key(hash), &media, validators.clone())
+                .await
+        }
+        None => remember(&transform.key(hash), &validators, data).await,
+    }
+
+    let mut response = Response::builder()
+        .header(CONTENT_TYPE, mime)
+        .header(CONTENT_LENGTH, media.len());
+
+    if let Some(etag) = validators.etag {
+        response = response.header(ETAG, etag);
+    }
+
+    if let Some(last_modified) = validators.last_modified {
+        response = response.header(LAST_MODIFIED, last_modified);
+    }
+
+    Ok(response.body(Body::from(media))?)
 }
 
 /// Proxies media from Threads
-#[tracing::instrument(err(Display), fields(error, hash))]
+#[tracing::instrument(err(Display), skip(headers, transform), fields(error, hash))]
 async fn serve(
     Path(hash): Path<String>,
     State(data): State<Arc<ShoelaceData>>,
+    Query(transform): Query<Transform>,
+    headers: HeaderMap,
 ) -> Result<Response, Error> {
-    let url: String = match &data.store {
-        Keystore::Internal(store) => {
-            let lock = store.lock().await;
+    // Signed links carry their own location, which is hashed to find it in the media cache
+    let signed = if signing::accepts(&hash, &data.config.proxy) {
+        Some(signing::verify(&hash, &data.config.proxy)?)
+    } else {
+        None
+    };
+
+    let hash = match &signed {
+        Some(url) => digest(url),
+        None => hash,
+    };
+
+    // Variants are cached separately from their source
+    let key = if transform.is_empty() {
+        hash.clone()
+    } else {
+        transform.key(&hash)
+    };
+
+    // Conditions are only checked once the object is known to exist, so made up links aren't
+    // reported as unchanged
+    let response = fetch(&data, &hash, &key, signed, &transform, &headers).await?;
+
+    Ok(conditional::finish(
+        response,
+        &headers,
+        conditional::IMMUTABLE,
+    ))
+}
 
-            match lock.get(&hash) {
-                Some(object) => object.to_owned(),
-                None => return Err(Error::ObjectNotFound),
+/// Fetches media from the cache, or from upstream
+async fn fetch(
+    data: &ShoelaceData,
+    hash: &str,
+    key: &str,
+    signed: Option<String>,
+    transform: &Transform,
+    headers: &HeaderMap,
+) -> Result<Response, Error> {
+    let range = headers.get(RANGE).and_then(|value| value.to_str().ok());
+
+    if let Some(cached) = match &data.media {
+        Some(cache) => cache.open(key).await,
+        None => None,
+    } {
+        return cached.respond(range.and_then(ByteRange::parse)).await;
+    }
+
+    let url = match signed {
+        Some(url) => url,
+        None if !data.store.enabled() => return Err(Error::NoProxy),
+        None => data.store.get(hash).await?.ok_or(Error::ObjectNotFound)?,
+    };
+
+    // Keystores may be shared, so their contents aren't trusted blindly
+    allowlist::check(&url, &data.config.proxy.allowed_hosts)?;
+
+    // Objects which were served before can be revalidated without going upstream
+    let recalled = recall(key, data).await;
+    if let Some(validators) = &recalled {
+        let last_modified = validators
+            .last_modified
+            .as_deref()
+            .and_then(|date| HeaderValue::from_str(date).ok());
+
+        if conditional::fresh(headers, validators.etag.as_deref(), last_modified.as_ref()) {
+            return Ok(conditional::not_modified(
+                validators.etag.as_deref(),
+                last_modified,
+                conditional::IMMUTABLE,
+            ));
+        }
+    }
+
+    if !transform.is_empty() {
+        // Originals which can't be transformed are served as they are
+        if let Some(cached) = match &data.media {
+            Some(cache) => cache.open(hash).await,
+            None => None,
+        } {
+            if cached.is_image() && cached.size() <= MAX_TRANSFORM_SIZE {
+                let (mime, last_modified) = (
+                    cached.mime().to_string(),
+                    cached.validators().last_modified.clone(),
+                );
+                let source = cached.bytes().await?;
+                return variant(hash, source, &mime, last_modified, transform, data).await;
             }
+
+            return cached.respond(range.and_then(ByteRange::parse)).await;
         }
-        Keystore::Redis(store) => {
-            let mut con = store.to_owned();
+    }
 
-            redis::cmd("GET")
-                .arg(hash)
-                .query_async(&mut con)
-                .await
-                .map_err(KeystoreError::RedisError)?
+    // Ranges are forwarded upstream, so only the requested bytes get fetched. Variants need the
+    // whole image, so the range is applied while streaming if it turns out not to be one
+    let mut request = allowlist::client(&data.config.proxy.allowed_hosts)?.get(url);
+
+    if let Some(range) = range.filter(|_| transform.is_empty()) {
+        request = request.header(RANGE, range);
+    }
+
+    let upstream = request.send().await?;
+
+    if upstream.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        let mut response = Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(ACCEPT_RANGES, "bytes");
+
+        if let Some(content_range) = upstream.headers().get(CONTENT_RANGE) {
+            response = response.header(CONTENT_RANGE, content_range);
         }
-        Keystore::None => return Err(Error::NoProxy),
+
+        return Ok(response.body(Body::empty())?);
+    }
+
+    let upstream = upstream.error_for_status()?;
+
+    let partial = upstream.status() == StatusCode::PARTIAL_CONTENT;
+    let length = upstream.content_length();
+    let content_range = upstream.headers().get(CONTENT_RANGE).cloned();
+    // Entity tags are hashed from the content, which isn't known until it's been streamed, so
+    // they're only sent once the object is remembered. Links are immutable, so a remembered tag
+    // still holds
+    let validators = Validators {
+        etag: None,
+        last_modified: upstream
+            .headers()
+            .get(LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from),
     };
+    let upstream_mime = upstream
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
 
-    let media = reqwest::get(url).await?.bytes().await?;
-    let mime = infer::get(&media);
+    // If upstream ignored the range, it gets applied while streaming instead
+    let bounds = match (partial, range.and_then(ByteRange::parse), length) {
+        (false, Some(requested), Some(total)) => match requested.resolve(total) {
+            Some(bounds) => Some((bounds, total)),
+            None => return unsatisfiable(total),
+        },
+        _ => None,
+    };
+
+    // The first chunk is enough to sniff the media's type, and the rest is streamed as is.
+    // Partial responses don't start with a signature, so upstream is trusted on those.
+    let mut media = upstream.bytes_stream();
+    let head = media.next().await.transpose()?.unwrap_or_default();
 
-    if let Some(mime_type) = mime {
-        Ok(Response::builder()
-            .header("Content-Type", mime_type.to_string())
-            .body(Body::from(media))?)
+    let sniffed = if partial {
+        None
     } else {
-        Err(Error::UnidentifiableMime)
+        infer::get(&head).map(|mime_type| mime_type.to_string())
+    };
+    let mime = sniffed.or(upstream_mime).ok_or(Error::UnidentifiableMime)?;
+
+    // Images are read into memory to be transformed, unless they turn out to be too large, in
+    // which case what was read so far is streamed along with the rest
+    let mut chunks = vec![head];
+
+    if !transform.is_empty()
+        && mime.starts_with("image/")
+        && length.is_none_or(|length| length <= MAX_TRANSFORM_SIZE)
+    {
+        let mut size = chunks[0].len() as u64;
+
+        while size <= MAX_TRANSFORM_SIZE {
+            match media.next().await.transpose()? {
+                Some(chunk) => {
+                    size += chunk.len() as u64;
+                    chunks.push(chunk);
+                }
+                None => {
+                    let (source, last_modified) = (chunks.concat(), validators.last_modified);
+                    return variant(hash, source, &mime, last_modified, transform, data).await;
+                }
+            }
+        }
+    }
+
+    let mut response = Response::builder()
+        .header(CONTENT_TYPE, mime)
+        .header(ACCEPT_RANGES, "bytes");
+
+    if let Some(etag) = recalled.and_then(|recalled| recalled.etag) {
+        response = response.header(ETAG, etag);
+    }
+
+    if let Some(last_modified) = &validators.last_modified {
+        response = response.header(LAST_MODIFIED, last_modified);
+    }
+
+    let body = stream::iter(chunks.into_iter().map(Ok)).chain(media);
+
+    if let Some(((start, end), total)) = bounds {
+        response = response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, total))
+            .header(CONTENT_LENGTH, end - start + 1);
+
+        return Ok(response.body(Body::from_stream(range::slice(body, start, end)))?);
+    }
+
+    if partial {
+        response = response.status(StatusCode::PARTIAL_CONTENT);
+
+        if let Some(content_range) = content_range {
+            response = response.header(CONTENT_RANGE, content_range);
+        }
+    }
+
+    if let Some(length) = length {
+        response = response.header(CONTENT_LENGTH, length);
+    }
+
+    // Only complete objects are worth storing, or hashing
+    match (&data.media, partial, range) {
+        (Some(cache), false, None) => {
+            Ok(response.body(Body::from_stream(cache.tee(hash, body, validators).await?))?)
+        }
+        (None, false, None) => {
+            Ok(response.body(Body::from_stream(remembering(key, body, validators, data)))?)
+        }
+        _ => Ok(response.body(Body::from_stream(body))?),
+    }
+}
+
+/// Looks up the validators remembered for an object
+async fn recall(key: &str, data: &ShoelaceData) -> Option<Validators> {
+    if !data.store.enabled() {
+        return None;
     }
+
+    match data.store.get(&format!("validators:{}", key)).await {
+        Ok(stored) => stored.map(|stored| Validators::parse(&stored)),
+        Err(error) => {
+            warn!("Couldn't look up validators for {}: {}", key, error);
+            None
+        }
+    }
+}
+
+/// Remembers an object's validators, for as long as links are kept
+async fn remember(key: &str, validators: &Validators, data: &ShoelaceData) {
+    if !data.store.enabled() {
+        return;
+    }
+
+    let ttl = match data.config.proxy.ttl {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+
+    if let Err(error) = data
+        .store
+        .set(&format!("validators:{}", key), &validators.write(), ttl)
+        .await
+    {
+        warn!("Couldn't remember validators for {}: {}", key, error);
+    }
+}
+
+/// Hashes media while it's being served, remembering its entity tag once it's complete
+fn remembering<S, E>(
+    key: &str,
+    media: S,
+    validators: Validators,
+    data: &ShoelaceData,
+) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+{
+    let state = Some((
+        Box::pin(media),
+        Blake2s256::new(),
+        (key.to_string(), validators, data.clone()),
+    ));
+
+    stream::unfold(state, |state| async move {
+        let (mut media, mut hasher, (key, mut validators, data)) = state?;
+
+        match media.next().await {
+            Some(Ok(chunk)) => {
+                hasher.update(&chunk);
+                Some((Ok(chunk), Some((media, hasher, (key, validators, data)))))
+            }
+            Some(Err(error)) => Some((Err(error), None)),
+            None => {
+                validators.etag = Some(conditional::hashed(hasher));
+                remember(&key, &validators, &data).await;
+                None
+            }
+        }
+    })
 }
@@ -0,0 +1,80 @@
+use axum::body::Bytes;
+use futures::{future, Stream, StreamExt};
+
+/// Single byte range, as requested through a Range header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteRange {
+    /// Starting at an offset, and optionally ending at another one (inclusive)
+    From(u64, Option<u64>),
+    /// Last bytes of an object
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Parses a Range header. Multiple ranges aren't supported, and are ignored
+    pub fn parse(header: &str) -> Option<Self> {
+        let spec = header.trim().strip_prefix("bytes=")?;
+
+        if spec.contains(',') {
+            return None;
+        }
+
+        let (start, end) = spec.trim().split_once('-')?;
+
+        if start.is_empty() {
+            return Some(Self::Suffix(end.parse().ok()?));
+        }
+
+        let start: u64 = start.parse().ok()?;
+        let end: Option<u64> = if end.is_empty() {
+            None
+        } else {
+            Some(end.parse().ok()?)
+        };
+
+        match end {
+            Some(end) if end < start => None,
+            _ => Some(Self::From(start, end)),
+        }
+    }
+
+    /// Resolves the range into inclusive bounds within an object of a given length
+    pub fn resolve(&self, length: u64) -> Option<(u64, u64)> {
+        if length == 0 {
+            return None;
+        }
+
+        match *self {
+            Self::From(start, _) if start >= length => None,
+            Self::From(start, end) => Some((start, end.unwrap_or(u64::MAX).min(length - 1))),
+            Self::Suffix(0) => None,
+            Self::Suffix(suffix) => Some((length.saturating_sub(suffix), length - 1)),
+        }
+    }
+}
+
+/// Trims a byte stream down to a given range
+pub fn slice<S, E>(media: S, start: u64, end: u64) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    let state = (start, end - start + 1);
+
+    media.scan(state, |(skip, remaining), item| {
+        if *remaining == 0 {
+            return future::ready(None);
+        }
+
+        future::ready(Some(item.map(|mut chunk| {
+            let skipped = (*skip).min(chunk.len() as u64);
+            *skip -= skipped;
+            let mut chunk = chunk.split_off(skipped as usize);
+
+            let kept = (*remaining).min(chunk.len() as u64);
+            *remaining -= kept;
+            chunk.truncate(kept as usize);
+
+            chunk
+        })))
+    })
+}
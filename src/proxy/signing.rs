@@ -0,0 +1,92 @@
+use crate::{config::Proxy, proxy::Error};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use core::fmt;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Secret shipped with the sample config, which anyone could sign links with
+const PLACEHOLDER_SECRET: &str = "change me";
+
+/// Ways in which media links can be generated
+#[derive(Debug, Deserialize, Clone)]
+pub enum Modes {
+    /// Links are hashes, mapped to their source within the keystore
+    Keystore,
+    /// Links embed their source, alongside a signature
+    Signed,
+}
+
+/// Builds a MAC from the configured secret
+fn mac(config: &Proxy) -> Result<HmacSha256, Error> {
+    match &config.secret {
+        Some(secret) if !secret.is_empty() => {
+            HmacSha256::new_from_slice(secret.as_bytes()).map_err(|_| Error::MissingSecret)
+        }
+        _ => Err(Error::MissingSecret),
+    }
+}
+
+/// Checks whether links can be signed with the configured secret
+pub fn check(config: &Proxy) -> Result<(), Error> {
+    if config.secret.as_deref() == Some(PLACEHOLDER_SECRET) {
+        return Err(Error::PlaceholderSecret);
+    }
+
+    mac(config).map(|_| ())
+}
+
+/// Encodes a URL into a signed identifier
+pub fn sign(url: &str, config: &Proxy) -> Result<String, Error> {
+    let mut mac = mac(config)?;
+    mac.update(url.as_bytes());
+
+    Ok(format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(url),
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    ))
+}
+
+/// Checks whether an identifier looks like a signed one
+pub fn is_signed(id: &str) -> bool {
+    id.contains('.')
+}
+
+/// Checks whether an identifier should be verified as a signed one. Outside of signed mode,
+/// they're looked up in the keystore like any other
+pub fn accepts(id: &str, config: &Proxy) -> bool {
+    matches!(config.mode, Modes::Signed) && is_signed(id)
+}
+
+/// Verifies a signed identifier, and decodes its URL
+pub fn verify(id: &str, config: &Proxy) -> Result<String, Error> {
+    let (payload, signature) = id.split_once('.').ok_or(Error::InvalidSignature)?;
+
+    let url = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|_| Error::InvalidSignature)?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| Error::InvalidSignature)?;
+
+    let mut mac = mac(config)?;
+    mac.update(&url);
+    mac.verify_slice(&signature)
+        .map_err(|_| Error::InvalidSignature)?;
+
+    String::from_utf8(url).map_err(|_| Error::InvalidSignature)
+}
+
+impl fmt::Display for Modes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::fmt::Result {
+        let out = match self {
+            Modes::Keystore => "keystore",
+            Modes::Signed => "signed",
+        };
+
+        write!(f, "{}", out)
+    }
+}
@@ -0,0 +1,114 @@
+use crate::proxy::Error;
+use image::{imageops::FilterType, DynamicImage, ImageFormat};
+use serde::Deserialize;
+use std::io::Cursor;
+
+/// Dimensions images can be resized to, so only a handful of variants exist per image
+const SIZES: [u32; 6] = [48, 96, 192, 384, 768, 1536];
+
+/// Output formats for transformed images
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Webp,
+    Jpeg,
+    Png,
+}
+
+/// Optional transformations for proxied images, requested through query parameters
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct Transform {
+    /// Maximum width
+    pub w: Option<u32>,
+    /// Maximum height
+    pub h: Option<u32>,
+    /// Output format. Defaults to the source's format, if supported
+    pub format: Option<Format>,
+}
+
+impl Format {
+    fn image_format(&self) -> ImageFormat {
+        match self {
+            Format::Webp => ImageFormat::WebP,
+            Format::Jpeg => ImageFormat::Jpeg,
+            Format::Png => ImageFormat::Png,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Format::Webp => "webp",
+            Format::Jpeg => "jpeg",
+            Format::Png => "png",
+        }
+    }
+}
+
+impl Transform {
+    /// Checks whether any transformation was requested
+    pub fn is_empty(&self) -> bool {
+        self.w.is_none() && self.h.is_none() && self.format.is_none()
+    }
+
+    /// Rounds dimensions up to the nearest supported size, so variants can't be abused
+    fn bounds(&self) -> (Option<u32>, Option<u32>) {
+        let round = |value: Option<u32>| {
+            value.map(|value| {
+                SIZES
+                    .into_iter()
+                    .find(|size| *size >= value)
+                    .unwrap_or(SIZES[SIZES.len() - 1])
+            })
+        };
+
+        (round(self.w), round(self.h))
+    }
+
+    /// Generates the media cache key for a variant of an object
+    pub fn key(&self, hash: &str) -> String {
+        let (width, height) = self.bounds();
+
+        format!(
+            "{}.{}x{}.{}",
+            hash,
+            width.unwrap_or_default(),
+            height.unwrap_or_default(),
+            self.format.map(|format| format.name()).unwrap_or("auto")
+        )
+    }
+
+    /// Resizes and encodes an image, returning it alongside its MIME type
+    pub fn apply(&self, source: &[u8]) -> Result<(Vec<u8>, &'static str), Error> {
+        let source_format = image::guess_format(source)?;
+        let mut image = image::load_from_memory_with_format(source, source_format)?;
+
+        let (width, height) = self.bounds();
+
+        // Images are only ever scaled down, keeping their aspect ratio
+        if width.is_some_and(|width| width < image.width())
+            || height.is_some_and(|height| height < image.height())
+        {
+            image = image.resize(
+                width.unwrap_or(u32::MAX),
+                height.unwrap_or(u32::MAX),
+                FilterType::Lanczos3,
+            );
+        }
+
+        let format = match (self.format, source_format) {
+            (Some(format), _) => format.image_format(),
+            (None, ImageFormat::Png | ImageFormat::WebP) => source_format,
+            (None, _) => ImageFormat::Jpeg,
+        };
+
+        // JPEG can't hold an alpha channel
+        if format == ImageFormat::Jpeg {
+            image = DynamicImage::ImageRgb8(image.to_rgb8());
+        }
+
+        let mut output = Cursor::new(Vec::new());
+        image.write_to(&mut output, format)?;
+
+        Ok((output.into_inner(), format.to_mime_type()))
+    }
+}
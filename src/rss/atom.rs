@@ -0,0 +1,93 @@
+use crate::rss::Feed;
+use askama_axum::IntoResponse;
+use atom_syndication::{Content, EntryBuilder, FeedBuilder, Link, LinkBuilder, Person, Text};
+use axum::{
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::Response,
+};
+
+/// Renders a feed as Atom
+pub fn render(feed: Feed) -> Response {
+    let entries: Vec<atom_syndication::Entry> = feed
+        .entries
+        .iter()
+        .map(|entry| {
+            let enclosures: Vec<Link> = entry
+                .media
+                .iter()
+                .map(|object| {
+                    LinkBuilder::default()
+                        .href(object.url.clone())
+                        .rel("enclosure")
+                        .mime_type(Some(object.mime.clone()))
+                        .length(Some(object.length.to_string()))
+                        .title((!object.alt.is_empty()).then(|| object.alt.clone()))
+                        .build()
+                })
+                .collect();
+
+            EntryBuilder::default()
+                .id(entry.guid.clone())
+                .title(Text::plain(entry.title.clone()))
+                .link(
+                    LinkBuilder::default()
+                        .href(entry.link.clone())
+                        .rel("alternate")
+                        .build(),
+                )
+                .links(enclosures)
+                .author(Person {
+                    name: format!("@{}", entry.author),
+                    email: None,
+                    uri: Some(entry.author_link.clone()),
+                })
+                .content(Content {
+                    value: Some(entry.html.clone()),
+                    content_type: Some(String::from("html")),
+                    ..Default::default()
+                })
+                .published(Some(entry.date.fixed_offset()))
+                .updated(entry.date)
+                .build()
+        })
+        .collect();
+
+    let atom = FeedBuilder::default()
+        .id(feed.link.clone())
+        .title(Text::plain(feed.title.clone()))
+        .subtitle(Some(Text::plain(feed.description.clone())))
+        .updated(feed.updated)
+        .authors(
+            feed.owner
+                .iter()
+                .map(|owner| Person {
+                    name: owner.name.clone(),
+                    email: None,
+                    uri: Some(owner.link.clone()),
+                })
+                .collect::<Vec<Person>>(),
+        )
+        .link(
+            LinkBuilder::default()
+                .href(feed.link.clone())
+                .rel("alternate")
+                .build(),
+        )
+        .link(
+            LinkBuilder::default()
+                .href(feed.location("atom"))
+                .rel("self")
+                .mime_type(Some(String::from("application/atom+xml")))
+                .build(),
+        )
+        .icon(feed.owner.as_ref().map(|owner| owner.icon.clone()))
+        .entries(entries)
+        .build();
+
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, "application/atom+xml")],
+        atom.to_string(),
+    )
+        .into_response()
+}
@@ -0,0 +1,126 @@
+use crate::rss::{Enclosure, Feed};
+use askama_axum::IntoResponse;
+use axum::{
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::Response,
+};
+use chrono::Utc;
+use rss::{
+    extension::{Extension, ExtensionBuilder, ExtensionMap},
+    ChannelBuilder, EnclosureBuilder, GuidBuilder, ImageBuilder, Item, ItemBuilder,
+};
+use std::collections::BTreeMap;
+
+/// Media RSS namespace, used to list every media object within an item
+const MEDIA_NAMESPACE: &str = "http://search.yahoo.com/mrss/";
+
+/// Describes a media object as a Media RSS element
+fn media(enclosure: &Enclosure) -> Extension {
+    let mut children = BTreeMap::new();
+
+    children.insert(
+        String::from("thumbnail"),
+        vec![ExtensionBuilder::default()
+            .name("media:thumbnail")
+            .attr(("url".to_string(), enclosure.thumbnail.clone()))
+            .build()],
+    );
+
+    if !enclosure.alt.is_empty() {
+        children.insert(
+            String::from("description"),
+            vec![ExtensionBuilder::default()
+                .name("media:description")
+                .value(Some(enclosure.alt.clone()))
+                .build()],
+        );
+    }
+
+    ExtensionBuilder::default()
+        .name("media:content")
+        .attr(("url".to_string(), enclosure.url.clone()))
+        .attr(("type".to_string(), enclosure.mime.clone()))
+        .attr(("fileSize".to_string(), enclosure.length.to_string()))
+        .attr((
+            "medium".to_string(),
+            String::from(if enclosure.video { "video" } else { "image" }),
+        ))
+        .children(children)
+        .build()
+}
+
+/// Renders a feed as RSS 2.0
+pub fn render(feed: Feed) -> Response {
+    let items: Vec<Item> = feed
+        .entries
+        .iter()
+        .map(|entry| {
+            // RSS only allows a single enclosure per item, so the rest are listed through Media RSS
+            let enclosure = entry.media.first().map(|object| {
+                EnclosureBuilder::default()
+                    .url(object.url.clone())
+                    .mime_type(object.mime.clone())
+                    .length(object.length.to_string())
+                    .build()
+            });
+
+            let mut extensions = ExtensionMap::new();
+
+            if !entry.media.is_empty() {
+                extensions.insert(
+                    String::from("media"),
+                    BTreeMap::from([(
+                        String::from("content"),
+                        entry.media.iter().map(media).collect(),
+                    )]),
+                );
+            }
+
+            ItemBuilder::default()
+                .title(entry.title.clone())
+                .link(entry.link.clone())
+                .guid(
+                    GuidBuilder::default()
+                        .value(entry.guid.clone())
+                        .permalink(true)
+                        .build(),
+                )
+                .description(entry.body.clone())
+                .content(entry.html.clone())
+                .enclosure(enclosure)
+                .extensions(extensions)
+                .author(format!("@{}", entry.author))
+                .pub_date(entry.date.to_rfc2822())
+                .build()
+        })
+        .collect();
+
+    let pfp = feed.owner.as_ref().map(|owner| {
+        ImageBuilder::default()
+            .title(format!("@{}'s profile picture", owner.username))
+            .url(owner.icon.clone())
+            .build()
+    });
+
+    let channel = ChannelBuilder::default()
+        .namespaces(BTreeMap::from([(
+            String::from("media"),
+            String::from(MEDIA_NAMESPACE),
+        )]))
+        .title(feed.title)
+        .link(feed.link)
+        .description(feed.description)
+        .image(pfp)
+        .last_build_date(Utc::now().to_rfc2822())
+        // Readers are told to wait for as long as the feed's contents are cached, in minutes
+        .ttl(feed.ttl.map(|ttl| ttl.div_ceil(60).max(1).to_string()))
+        .items(items)
+        .build();
+
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, "application/rss+xml")],
+        channel.to_string(),
+    )
+        .into_response()
+}
@@ -0,0 +1,51 @@
+use serde::Deserialize;
+use spools::Subpost;
+
+/// Filters applied over a feed's posts, requested through query parameters. There's no filter for
+/// replies, since profiles don't mark which of their posts are replies; thread feeds leave them out
+/// through [`ThreadOptions`](super::ThreadOptions) instead
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct Filters {
+    /// Only keep posts with images or videos
+    pub media_only: bool,
+    /// Comma-separated keywords, at least one of which posts must contain
+    pub include: Option<String>,
+    /// Comma-separated keywords posts must not contain
+    pub exclude: Option<String>,
+    /// Most posts to keep, starting from the newest ones
+    pub limit: Option<usize>,
+}
+
+/// Splits a comma-separated list of keywords, for case-insensitive matching
+fn keywords(list: &Option<String>) -> Vec<String> {
+    list.iter()
+        .flat_map(|list| list.split(','))
+        .map(|keyword| keyword.trim().to_lowercase())
+        .filter(|keyword| !keyword.is_empty())
+        .collect()
+}
+
+impl Filters {
+    /// Checks whether a post passes every filter
+    fn matches(&self, post: &Subpost, include: &[String], exclude: &[String]) -> bool {
+        let body = post.body.to_lowercase();
+
+        (!self.media_only || !post.media.is_empty())
+            && (include.is_empty() || include.iter().any(|keyword| body.contains(keyword)))
+            && !exclude.iter().any(|keyword| body.contains(keyword))
+    }
+
+    /// Drops posts which don't pass the filters
+    pub fn apply<T>(&self, posts: &mut Vec<(Subpost, T)>) {
+        let include = keywords(&self.include);
+        let exclude = keywords(&self.exclude);
+
+        posts.retain(|(post, _)| self.matches(post, &include, &exclude));
+
+        if let Some(limit) = self.limit {
+            posts.sort_by_key(|(post, _)| std::cmp::Reverse(post.date));
+            posts.truncate(limit);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::rss::Feed;
+use askama_axum::IntoResponse;
+use axum::{
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::Response,
+    Json,
+};
+use serde::Serialize;
+
+/// Version URL identifying JSON Feed 1.1
+const VERSION: &str = "https://jsonfeed.org/version/1.1";
+
+/// JSON Feed document
+#[derive(Debug, Serialize)]
+struct Document {
+    version: &'static str,
+    title: String,
+    home_page_url: String,
+    feed_url: String,
+    description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<Author>,
+    items: Vec<Item>,
+}
+
+/// JSON Feed author
+#[derive(Debug, Serialize)]
+struct Author {
+    name: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    avatar: Option<String>,
+}
+
+/// JSON Feed item
+#[derive(Debug, Serialize)]
+struct Item {
+    id: String,
+    url: String,
+    title: String,
+    content_html: String,
+    content_text: String,
+    date_published: String,
+    authors: Vec<Author>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<Attachment>,
+}
+
+/// JSON Feed attachment
+#[derive(Debug, Serialize)]
+struct Attachment {
+    url: String,
+    mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_in_bytes: Option<u64>,
+}
+
+/// Renders a feed as JSON Feed 1.1
+pub fn render(feed: Feed) -> Response {
+    let items: Vec<Item> = feed
+        .entries
+        .iter()
+        .map(|entry| Item {
+            id: entry.guid.clone(),
+            url: entry.link.clone(),
+            title: entry.title.clone(),
+            content_html: entry.html.clone(),
+            content_text: entry.body.clone(),
+            date_published: entry.date.to_rfc3339(),
+            authors: vec![Author {
+                name: format!("@{}", entry.author),
+                url: entry.author_link.clone(),
+                avatar: None,
+            }],
+            attachments: entry
+                .media
+                .iter()
+                .map(|object| Attachment {
+                    url: object.url.clone(),
+                    mime_type: object.mime.clone(),
+                    title: (!object.alt.is_empty()).then(|| object.alt.clone()),
+                    size_in_bytes: (object.length > 0).then_some(object.length),
+                })
+                .collect(),
+        })
+        .collect();
+
+    let document = Document {
+        version: VERSION,
+        title: feed.title.clone(),
+        home_page_url: feed.link.clone(),
+        feed_url: feed.location("feed"),
+        description: feed.description.clone(),
+        icon: feed.owner.as_ref().map(|owner| owner.icon.clone()),
+        authors: feed
+            .owner
+            .iter()
+            .map(|owner| Author {
+                name: owner.name.clone(),
+                url: owner.link.clone(),
+                avatar: Some(owner.icon.clone()),
+            })
+            .collect(),
+        items,
+    };
+
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, "application/feed+json")],
+        Json(document),
+    )
+        .into_response()
+}
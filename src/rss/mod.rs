@@ -0,0 +1,681 @@
+mod atom;
+mod channel;
+mod filter;
+mod json;
+pub mod opml;
+
+pub use filter::Filters;
+
+use crate::{
+    common::error::Error,
+    frontend::formatters,
+    proxy::{self, conditional},
+    req, ShoelaceData,
+};
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State},
+    http::{
+        header::{ETAG, LAST_MODIFIED},
+        HeaderMap, HeaderValue,
+    },
+    response::Response,
+    routing::{get, post},
+    Router,
+};
+use blake2::{Blake2s256, Digest};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::Deserialize;
+use spools::{Media, MediaKind, Subpost};
+use std::{cmp::Reverse, sync::Arc};
+use tracing::warn;
+
+/// Threads' own location, which links point to when the frontend is disabled
+const THREADS_URL: &str = "https://www.threads.net";
+
+/// Longest title derived from a post's body, in characters
+const TITLE_LENGTH: usize = 80;
+
+/// Most entries expanded at once, each of which may look up its media through the proxy
+const EXPAND_CONCURRENCY: usize = 8;
+
+/// Most profiles scraped at once for a merged feed
+const MERGE_CONCURRENCY: usize = 8;
+
+/// Attaches the feeds module to an Axum router
+pub fn attach(enabled: bool) -> Router<Arc<ShoelaceData>> {
+    let mut routed = Router::new();
+
+    if enabled {
+        for format in [Format::Rss, Format::Atom, Format::Json] {
+            let prefix = format.prefix();
+
+            routed = routed
+                .route(
+                    &format!("/{}/:id", prefix),
+                    get(move |path, state, filters, headers| {
+                        user(format, path, state, filters, headers)
+                    }),
+                )
+                .route(
+                    &format!("/{}/t/:id", prefix),
+                    get(move |path, state, options, filters, headers| {
+                        thread(format, path, state, options, filters, headers)
+                    }),
+                )
+                .route(
+                    &format!("/{}/m/:list", prefix),
+                    get(move |path, state, filters, headers| {
+                        merged(format, path, state, filters, headers)
+                    }),
+                )
+        }
+
+        routed = routed
+            .route("/opml", post(opml::import))
+            .route("/opml/:list", get(opml::export))
+    }
+
+    routed
+}
+
+/// Formats feeds can be rendered to
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    /// RSS 2.0
+    Rss,
+    /// Atom
+    Atom,
+    /// JSON Feed 1.1
+    Json,
+}
+
+/// Feed, independent from the format it gets rendered to
+pub struct Feed {
+    /// Instance's base URL
+    pub base: String,
+    /// Feed's location, relative to each format's route
+    pub path: String,
+    /// Feed's title
+    pub title: String,
+    /// Link to what the feed follows
+    pub link: String,
+    /// Feed's description
+    pub description: String,
+    /// Account the feed belongs to. Merged feeds don't have one
+    pub owner: Option<Owner>,
+    /// Date of the latest post, or when the feed was built if there are none
+    pub updated: DateTime<Utc>,
+    /// Whether the feed has posts to date it with
+    modified: bool,
+    /// How long the feed's contents are cached for, in seconds
+    pub ttl: Option<u64>,
+    /// Posts within the feed, alongside a label for them, before being expanded into entries
+    posts: Vec<(Subpost, String)>,
+    /// Entries within the feed
+    pub entries: Vec<Entry>,
+}
+
+/// Account a feed belongs to
+pub struct Owner {
+    /// Owner's username
+    pub username: String,
+    /// Owner's display name
+    pub name: String,
+    /// Link to the owner's profile
+    pub link: String,
+    /// Owner's profile picture
+    pub icon: String,
+}
+
+/// Options for thread feeds
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ThreadOptions {
+    /// Include the posts being replied to
+    pub parents: bool,
+    /// Include the replies to the post
+    pub replies: bool,
+}
+
+impl Default for ThreadOptions {
+    fn default() -> Self {
+        Self {
+            parents: false,
+            replies: true,
+        }
+    }
+}
+
+impl ThreadOptions {
+    /// Lays out a thread's posts in order, labelled by their role within it
+    pub fn select(
+        &self,
+        post: Subpost,
+        parents: Vec<Subpost>,
+        replies: Vec<Subpost>,
+    ) -> Vec<(Subpost, String)> {
+        let parents = if self.parents { parents } else { Vec::new() };
+        let replies = if self.replies { replies } else { Vec::new() };
+        let label = format!("Post by @{}", post.author.username);
+
+        parents
+            .into_iter()
+            .map(|parent| {
+                let label = format!("Post by @{}", parent.author.username);
+                (parent, label)
+            })
+            .chain([(post, label)])
+            .chain(replies.into_iter().map(|reply| {
+                let label = format!("Reply by @{}", reply.author.username);
+                (reply, label)
+            }))
+            .collect()
+    }
+}
+
+/// Single post within a feed
+pub struct Entry {
+    /// Stable identifier, which is the post's permalink on Threads
+    pub guid: String,
+    /// Link to the post
+    pub link: String,
+    /// Title, since posts don't have one of their own
+    pub title: String,
+    /// Post's body
+    pub body: String,
+    /// Post's body and media, as HTML
+    pub html: String,
+    /// Post's media
+    pub media: Vec<Enclosure>,
+    /// Author's username
+    pub author: String,
+    /// Link to the author's profile
+    pub author_link: String,
+    /// Publishing date
+    pub date: DateTime<Utc>,
+}
+
+/// Media attached to an entry
+#[derive(Debug)]
+pub struct Enclosure {
+    /// Proxied location
+    pub url: String,
+    /// Preview image
+    pub thumbnail: String,
+    /// MIME type
+    pub mime: String,
+    /// Size in bytes, or zero if unknown
+    pub length: u64,
+    /// Alternative text
+    pub alt: String,
+    /// Whether the media is a video
+    pub video: bool,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "components/entry.j2")]
+struct Content<'a> {
+    body: &'a str,
+    media: &'a [Enclosure],
+}
+
+impl Enclosure {
+    /// Describes a media object, looking up its type and size through the proxy
+    async fn new(media: &Media, data: &ShoelaceData) -> Self {
+        let video = media.kind == MediaKind::Video;
+
+        // Failed lookups are already logged, and shouldn't take the whole feed down
+        let (mime, length) = proxy::probe(&media.content, data).await.unwrap_or_default();
+
+        Self {
+            url: media.content.clone(),
+            thumbnail: media.thumbnail.clone(),
+            mime: mime
+                .unwrap_or_else(|| String::from(if video { "video/mp4" } else { "image/jpeg" })),
+            length: length.unwrap_or_default(),
+            alt: media.alt.clone().unwrap_or_default(),
+            video,
+        }
+    }
+}
+
+/// Location links within feeds point to. The frontend, if enabled, or Threads otherwise
+fn linked(data: &ShoelaceData) -> &str {
+    if data.config.endpoint.frontend {
+        &data.config.server.base_url
+    } else {
+        THREADS_URL
+    }
+}
+
+/// Links to a post
+fn post_link(code: &str, username: &str, data: &ShoelaceData) -> String {
+    if data.config.endpoint.frontend {
+        format!("{}/t/{}", data.config.server.base_url, code)
+    } else {
+        format!("{}/@{}/post/{}", THREADS_URL, username, code)
+    }
+}
+
+/// Links to a profile
+fn profile_link(username: &str, data: &ShoelaceData) -> String {
+    format!("{}/@{}", linked(data), username)
+}
+
+/// Resolves users from either a named list or comma-separated usernames, alongside a title for them
+fn users(list: &str, data: &ShoelaceData) -> Result<(String, Vec<String>), Error> {
+    let config = &data.config.feeds;
+
+    let (title, mut users) = match config.lists.get(list) {
+        Some(users) => (list.to_string(), users.clone()),
+        None => (
+            String::from("Merged feed"),
+            list.split(',')
+                .map(str::trim)
+                .filter(|user| !user.is_empty())
+                .map(String::from)
+                .collect::<Vec<String>>(),
+        ),
+    };
+
+    users.sort_unstable();
+    users.dedup();
+
+    if users.is_empty() {
+        return Err(Error::NotFound);
+    }
+
+    if users.len() > config.max_users {
+        return Err(Error::TooManyUsers(config.max_users));
+    }
+
+    Ok((title, users))
+}
+
+/// Derives a title from a post's first line, shortened if needed. Posts without text don't get one
+pub fn title(body: &str) -> Option<String> {
+    let line = body.lines().map(str::trim).find(|line| !line.is_empty())?;
+
+    if line.chars().count() <= TITLE_LENGTH {
+        return Some(line.to_string());
+    }
+
+    let truncated: String = line.chars().take(TITLE_LENGTH - 1).collect();
+
+    Some(format!("{}…", truncated.trim_end()))
+}
+
+/// How long a kind of response is cached for, if caching is enabled
+fn ttl(seconds: u64, data: &ShoelaceData) -> Option<u64> {
+    data.config.cache.enabled.then_some(seconds)
+}
+
+impl Entry {
+    /// Builds an entry from a post
+    async fn new(post: &Subpost, label: &str, data: &ShoelaceData) -> Result<Self, Error> {
+        let date = Utc.timestamp_opt(post.date as i64, 0).unwrap();
+        // Media is looked up one object at a time, as entries are already expanded concurrently
+        let mut media = Vec::with_capacity(post.media.len());
+
+        for object in &post.media {
+            media.push(Enclosure::new(object, data).await);
+        }
+
+        let base = data.base.linking_to(linked(data));
+        let body = formatters::body(&post.body, &base)?.replace('\n', "<br>");
+        let html = Content {
+            body: &body,
+            media: &media,
+        }
+        .render()?;
+
+        let username = &post.author.username;
+
+        Ok(Self {
+            guid: format!("{}/@{}/post/{}", THREADS_URL, username, post.code),
+            link: post_link(&post.code, username, data),
+            title: title(&post.body)
+                .unwrap_or_else(|| format!("{} on {}", label, date.format("%Y-%m-%d"))),
+            body: post.body.clone(),
+            html,
+            media,
+            author: username.clone(),
+            author_link: profile_link(username, data),
+            date,
+        })
+    }
+}
+
+impl Feed {
+    /// Builds a feed for a profile
+    pub async fn user(user: &str, data: &ShoelaceData) -> Result<Self, Error> {
+        let response = req::user(user, data).await?;
+        let label = format!("Post by {}", response.name);
+
+        let posts = response
+            .posts
+            .into_iter()
+            .map(|post| (post, label.clone()))
+            .collect();
+
+        Ok(Self::new(
+            profile_link(user, data),
+            user.to_string(),
+            format!("{} (@{})", response.name, user),
+            response.bio,
+            Some(Owner {
+                username: user.to_string(),
+                name: format!("{} (@{})", response.name, user),
+                link: profile_link(user, data),
+                icon: response.pfp,
+            }),
+            ttl(data.config.cache.user_ttl, data),
+            posts,
+            data,
+        ))
+    }
+
+    /// Builds a feed for a post, followed by its replies
+    pub async fn thread(
+        code: &str,
+        options: &ThreadOptions,
+        data: &ShoelaceData,
+    ) -> Result<Self, Error> {
+        let response = req::post(code, data).await?;
+        let username = response.author.username.clone();
+
+        // The post itself isn't a subpost, so it's given its own code
+        let post = Subpost {
+            code: code.to_string(),
+            author: response.author.clone(),
+            date: response.date,
+            body: response.body.clone(),
+            media: response.media.clone(),
+            likes: response.likes,
+        };
+
+        let posts = options.select(post, response.parents, response.replies);
+
+        Ok(Self::new(
+            post_link(code, &username, data),
+            format!("t/{}", code),
+            format!("Thread by @{}", username),
+            response.body,
+            Some(Owner {
+                name: format!("@{}", username),
+                link: profile_link(&username, data),
+                username,
+                icon: response.author.pfp,
+            }),
+            ttl(data.config.cache.post_ttl, data),
+            posts,
+            data,
+        ))
+    }
+
+    /// Builds a feed merging several profiles, from either a named list or comma-separated usernames
+    pub async fn merged(list: &str, data: &ShoelaceData) -> Result<Self, Error> {
+        let config = &data.config.feeds;
+        let (title, users) = users(list, data)?;
+
+        let requests: Vec<_> = users.iter().map(|user| req::user(user, data)).collect();
+        let responses: Vec<_> = stream::iter(requests)
+            .buffered(MERGE_CONCURRENCY)
+            .collect()
+            .await;
+
+        // A single missing profile shouldn't take down the whole feed
+        let mut first_error = None;
+        let mut posts: Vec<(Subpost, String)> = Vec::new();
+
+        for (user, response) in users.iter().zip(responses) {
+            match response {
+                Ok(response) => {
+                    let label = format!("Post by {}", response.name);
+
+                    posts.extend(response.posts.into_iter().map(|post| (post, label.clone())));
+                }
+                Err(error) => {
+                    warn!("Skipping @{} in merged feed: {}", user, error);
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+
+        if posts.is_empty() {
+            if let Some(error) = first_error {
+                return Err(error);
+            }
+        }
+
+        posts.sort_by_key(|(post, _)| Reverse(post.date));
+        posts.truncate(config.max_items);
+
+        let description = format!(
+            "Posts by {}",
+            users
+                .iter()
+                .map(|user| format!("@{}", user))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+
+        Ok(Self::new(
+            linked(data).to_string(),
+            format!("m/{}", list),
+            title,
+            description,
+            None,
+            ttl(data.config.cache.user_ttl, data),
+            posts,
+            data,
+        ))
+    }
+
+    /// Spawns a feed out of posts, which still need to be expanded into entries
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        link: String,
+        path: String,
+        title: String,
+        description: String,
+        owner: Option<Owner>,
+        ttl: Option<u64>,
+        posts: Vec<(Subpost, String)>,
+        data: &ShoelaceData,
+    ) -> Self {
+        Self {
+            base: data.config.server.base_url.clone(),
+            path,
+            title,
+            link,
+            description,
+            owner,
+            updated: Utc::now(),
+            modified: false,
+            ttl,
+            posts,
+            entries: Vec::new(),
+        }
+        .date()
+    }
+
+    /// Dates the feed after its newest post
+    fn date(mut self) -> Self {
+        let latest = self.posts.iter().map(|(post, _)| post.date).max();
+
+        self.updated = latest
+            .and_then(|date| Utc.timestamp_opt(date as i64, 0).single())
+            .unwrap_or_else(Utc::now);
+        self.modified = latest.is_some();
+
+        self
+    }
+
+    /// Drops posts which don't pass a set of filters
+    pub fn filter(mut self, filters: &Filters) -> Self {
+        filters.apply(&mut self.posts);
+
+        self.date()
+    }
+
+    /// Builds entries out of the feed's posts, rendering their contents and looking up their media
+    pub async fn expand(mut self, data: &ShoelaceData) -> Result<Self, Error> {
+        let posts = std::mem::take(&mut self.posts);
+
+        let entries: Vec<_> = posts
+            .iter()
+            .map(|(post, label)| Entry::new(post, label, data))
+            .collect();
+
+        self.entries = stream::iter(entries)
+            .buffered(EXPAND_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        Ok(self)
+    }
+
+    /// Builds an entity tag for the feed in a given format, which changes along with its posts
+    pub fn etag(&self, format: Format) -> String {
+        let mut hasher = Blake2s256::new();
+
+        hasher.update(format.prefix());
+        hasher.update(&self.link);
+        hasher.update(&self.path);
+
+        for (post, _) in &self.posts {
+            hasher.update(&post.code);
+            hasher.update(post.date.to_be_bytes());
+        }
+
+        conditional::hashed(hasher)
+    }
+
+    /// Date of the newest post, formatted as an HTTP date. Feeds without posts don't have one
+    pub fn last_modified(&self) -> Option<String> {
+        self.modified
+            .then(|| conditional::http_date(self.updated.into()))
+    }
+
+    /// Link to the feed itself, in a given format
+    pub fn location(&self, format: &str) -> String {
+        format!("{}/{}/{}", self.base, format, self.path)
+    }
+}
+
+impl Format {
+    /// Route feeds in this format are served under
+    fn prefix(&self) -> &'static str {
+        match self {
+            Format::Rss => "rss",
+            Format::Atom => "atom",
+            Format::Json => "feed",
+        }
+    }
+
+    /// Strips the extension feed readers tend to expect from JSON Feed locations
+    fn id<'a>(&self, id: &'a str) -> &'a str {
+        match self {
+            Format::Json => id.strip_suffix(".json").unwrap_or(id),
+            _ => id,
+        }
+    }
+
+    /// Renders a feed in this format
+    fn render(&self, feed: Feed) -> Response {
+        match self {
+            Format::Rss => channel::render(feed),
+            Format::Atom => atom::render(feed),
+            Format::Json => json::render(feed),
+        }
+    }
+}
+
+/// Renders a feed, unless the client's copy is still fresh
+pub async fn respond(
+    format: Format,
+    feed: Result<Feed, Error>,
+    filters: &Filters,
+    headers: &HeaderMap,
+    data: &ShoelaceData,
+) -> Response {
+    let feed = match feed {
+        Ok(feed) => feed.filter(filters),
+        Err(error) => return error.into_plaintext(),
+    };
+
+    let cache_control = match data.config.feeds.max_age {
+        0 => String::from("no-cache"),
+        max_age => format!("public, max-age={}", max_age),
+    };
+
+    // Validators only depend on posts, so they're checked before looking up any media
+    let etag = feed.etag(format);
+    let last_modified = feed
+        .last_modified()
+        .and_then(|date| HeaderValue::from_str(&date).ok());
+
+    if conditional::fresh(headers, Some(&etag), last_modified.as_ref()) {
+        return conditional::not_modified(Some(&etag), last_modified, &cache_control);
+    }
+
+    match feed.expand(data).await {
+        Ok(feed) => {
+            let mut response = format.render(feed);
+
+            if let Some(last_modified) = last_modified {
+                response.headers_mut().insert(LAST_MODIFIED, last_modified);
+            }
+
+            if let Ok(etag) = HeaderValue::from_str(&etag) {
+                response.headers_mut().insert(ETAG, etag);
+            }
+
+            conditional::finish(response, headers, &cache_control)
+        }
+        Err(error) => error.into_plaintext(),
+    }
+}
+
+/// Feed for a profile
+async fn user(
+    format: Format,
+    Path(user): Path<String>,
+    State(data): State<Arc<ShoelaceData>>,
+    Query(filters): Query<Filters>,
+    headers: HeaderMap,
+) -> Response {
+    let feed = Feed::user(format.id(&user), &data).await;
+
+    respond(format, feed, &filters, &headers, &data).await
+}
+
+/// Feed for a thread
+async fn thread(
+    format: Format,
+    Path(post): Path<String>,
+    State(data): State<Arc<ShoelaceData>>,
+    Query(options): Query<ThreadOptions>,
+    Query(filters): Query<Filters>,
+    headers: HeaderMap,
+) -> Response {
+    let feed = Feed::thread(format.id(&post), &options, &data).await;
+
+    respond(format, feed, &filters, &headers, &data).await
+}
+
+/// Feed merging several profiles
+async fn merged(
+    format: Format,
+    Path(list): Path<String>,
+    State(data): State<Arc<ShoelaceData>>,
+    Query(filters): Query<Filters>,
+    headers: HeaderMap,
+) -> Response {
+    let feed = Feed::merged(format.id(&list), &data).await;
+
+    respond(format, feed, &filters, &headers, &data).await
+}
@@ -0,0 +1,173 @@
+use crate::{
+    common::error::Error,
+    rss::{profile_link, users},
+    ShoelaceData,
+};
+use askama_axum::IntoResponse;
+use axum::{
+    extract::{Path, State},
+    http::{
+        header::{CONTENT_TYPE, LOCATION},
+        StatusCode,
+    },
+    response::Response,
+};
+use chrono::Utc;
+use quick_xml::{
+    events::{BytesDecl, BytesText, Event},
+    Reader, Writer,
+};
+use std::sync::Arc;
+
+/// Checks whether a string could be a Threads username
+fn valid(username: &str) -> bool {
+    !username.is_empty()
+        && username.len() <= 30
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_')
+}
+
+/// Finds the username within a link to one of this instance's profile feeds
+fn from_feed(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?.trim_end_matches('/');
+    let (rest, id) = path.rsplit_once('/')?;
+    let (_, prefix) = rest.rsplit_once('/')?;
+
+    let username = match prefix {
+        "rss" | "atom" => id,
+        "feed" => id.strip_suffix(".json").unwrap_or(id),
+        _ => return None,
+    };
+
+    valid(username).then(|| username.to_string())
+}
+
+/// Finds the username within a link to a profile, be it on this instance or on Threads
+fn from_profile(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("/@")?;
+    let username = rest.split(['/', '?', '#']).next()?;
+
+    valid(username).then(|| username.to_string())
+}
+
+/// Reads the usernames behind an OPML document's feeds
+pub fn usernames(document: &str) -> Result<Vec<String>, Error> {
+    let mut reader = Reader::from_str(document);
+    let mut users = Vec::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) | Event::Empty(element)
+                if element.local_name().as_ref() == b"outline" =>
+            {
+                let attribute = |name: &str| {
+                    element
+                        .try_get_attribute(name)
+                        .ok()
+                        .flatten()
+                        .and_then(|attribute| attribute.decode_and_unescape_value(&reader).ok())
+                        .map(|value| value.to_string())
+                };
+
+                let user = attribute("xmlUrl")
+                    .and_then(|url| from_feed(&url))
+                    .or_else(|| attribute("htmlUrl").and_then(|url| from_profile(&url)));
+
+                if let Some(user) = user {
+                    users.push(user);
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+
+    users.sort_unstable();
+    users.dedup();
+
+    Ok(users)
+}
+
+/// Writes an OPML document pointing at feeds for a list of users
+pub fn document(list: &str, data: &ShoelaceData) -> Result<String, Error> {
+    let (title, users) = users(list, data)?;
+    let base = &data.config.server.base_url;
+
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+
+    writer
+        .create_element("opml")
+        .with_attribute(("version", "2.0"))
+        .write_inner_content(|writer| {
+            writer
+                .create_element("head")
+                .write_inner_content(|writer| {
+                    writer
+                        .create_element("title")
+                        .write_text_content(BytesText::new(&title))?;
+                    writer
+                        .create_element("dateCreated")
+                        .write_text_content(BytesText::new(&Utc::now().to_rfc2822()))?;
+
+                    Ok::<(), quick_xml::Error>(())
+                })?;
+
+            writer
+                .create_element("body")
+                .write_inner_content(|writer| {
+                    for user in &users {
+                        let name = format!("@{}", user);
+
+                        writer
+                            .create_element("outline")
+                            .with_attribute(("type", "rss"))
+                            .with_attribute(("text", name.as_str()))
+                            .with_attribute(("title", name.as_str()))
+                            .with_attribute(("xmlUrl", format!("{}/rss/{}", base, user).as_str()))
+                            .with_attribute(("htmlUrl", profile_link(user, data).as_str()))
+                            .write_empty()?;
+                    }
+
+                    Ok::<(), quick_xml::Error>(())
+                })?;
+
+            Ok::<(), quick_xml::Error>(())
+        })?;
+
+    Ok(String::from_utf8_lossy(&writer.into_inner()).to_string())
+}
+
+/// Exports a list of users as OPML
+pub async fn export(Path(list): Path<String>, State(data): State<Arc<ShoelaceData>>) -> Response {
+    match document(&list, &data) {
+        Ok(document) => (StatusCode::OK, [(CONTENT_TYPE, "text/x-opml")], document).into_response(),
+        Err(error) => error.into_plaintext(),
+    }
+}
+
+/// Imports an OPML document, redirecting to a merged feed for every profile within it
+pub async fn import(State(data): State<Arc<ShoelaceData>>, document: String) -> Response {
+    let users = match usernames(&document) {
+        Ok(users) => users,
+        Err(error) => return error.into_plaintext(),
+    };
+
+    if users.is_empty() {
+        return Error::NoFeeds.into_plaintext();
+    }
+
+    if users.len() > data.config.feeds.max_users {
+        return Error::TooManyUsers(data.config.feeds.max_users).into_plaintext();
+    }
+
+    let location = format!("{}/rss/m/{}", data.config.server.base_url, users.join(","));
+
+    (
+        StatusCode::SEE_OTHER,
+        [(LOCATION, location.clone())],
+        location,
+    )
+        .into_response()
+}
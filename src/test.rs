@@ -1,22 +1,121 @@
 use crate::{
-    api,
-    common::config::Settings,
+    api::{
+        self,
+        auth::{self, Client, Scope, Tokens},
+    },
+    common::{
+        cache::{self, Kind},
+        config::{self, Settings, Token},
+        error::Code,
+        flight::Flights,
+        limiter::Limiter,
+        req::{self, Inflight},
+    },
     frontend::{self, Base},
-    proxy, ShoelaceData,
+    limiter, logger,
+    proxy::{
+        self, allowlist, conditional,
+        keystore::{Disabled, Internal, Keystore, Sqlite},
+        range, signing,
+        transform::{Format, Transform},
+        ByteRange, MediaCache, Validators,
+    },
+    rss::{self, opml, Feed, Filters, ThreadOptions},
+    Error, ShoelaceData,
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::connect_info::MockConnectInfo,
+    http::{
+        header::{CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+        HeaderMap, HeaderName, HeaderValue, StatusCode,
+    },
+    middleware,
+    response::Response,
+    routing::get,
+    Router,
 };
-use axum::{http::StatusCode, Router};
 use axum_test::TestServer;
-use spools::{Post, User};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde_json::json;
+use spools::{Author, Media, MediaKind, Post, Subpost, User};
 use std::{
-    collections::HashMap,
-    sync::{Arc, LazyLock},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock,
+    },
+    time::{Duration, SystemTime},
 };
-use tokio::sync::Mutex;
+
+/// Builds a post by Zuck, optionally with an image attached
+fn post(code: &str, date: u64, body: &str, media: bool) -> Subpost {
+    Subpost {
+        code: code.to_string(),
+        author: Author {
+            username: String::from("zuck"),
+            pfp: String::new(),
+            verified: true,
+        },
+        date,
+        body: body.to_string(),
+        media: if media {
+            vec![Media {
+                kind: MediaKind::Image,
+                alt: None,
+                content: String::new(),
+                thumbnail: String::new(),
+            }]
+        } else {
+            Vec::new()
+        },
+        likes: 0,
+    }
+}
+
+/// Builds a profile feed out of posts
+fn feed(posts: Vec<Subpost>, data: &ShoelaceData) -> Feed {
+    Feed::new(
+        String::from("https://www.threads.net/@zuck"),
+        String::from("zuck"),
+        String::from("Mark Zuckerberg (@zuck)"),
+        String::new(),
+        None,
+        None,
+        posts
+            .into_iter()
+            .map(|post| (post, String::from("Post by Mark Zuckerberg")))
+            .collect(),
+        data,
+    )
+}
+
+/// Renders a feed in full, as a client without a copy of it would get it
+async fn rendered(format: rss::Format, feed: Feed, data: &ShoelaceData) -> String {
+    let response = rss::respond(
+        format,
+        Ok(feed),
+        &Filters::default(),
+        &HeaderMap::new(),
+        data,
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
 
 static TEST_APP_DATA: LazyLock<ShoelaceData> = LazyLock::new(|| ShoelaceData {
-    store: crate::proxy::Keystore::None,
+    store: Arc::new(Disabled),
+    media: None,
     base: Base::new().unwrap(),
     config: Settings::new().unwrap(),
+    tokens: Arc::new(Tokens::default()),
+    clients: Limiter::default(),
+    inflight: Inflight::default(),
 });
 
 #[tokio::test]
@@ -73,15 +172,337 @@ async fn post_api() {
     assert_eq!(post.id, "3283131293873103989")
 }
 
+#[tokio::test]
+async fn openapi() {
+    let app = Router::new()
+        .nest("/api/", api::attach(true))
+        .with_state(Arc::new(TEST_APP_DATA.clone()));
+    let server = TestServer::new(app).unwrap();
+
+    let response = server.get("/api/openapi.json").await;
+    let document: serde_json::Value = response.json();
+
+    assert!(document["paths"]["/api/v1/user/{username}"]["get"].is_object());
+    assert!(document["paths"]["/api/v1/post/{code}"]["get"].is_object());
+    assert!(document["components"]["schemas"]["Thread"].is_object());
+}
+
+#[tokio::test]
+async fn errors() {
+    assert_eq!(Error::NotFound.code(), Code::NotFound);
+    assert_eq!(
+        Error::Proxy(proxy::Error::NoProxy).code().status(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    let data = Arc::new(TEST_APP_DATA.clone());
+    let app = Router::new()
+        .route("/", get(|| async { Error::NoFeeds.into_json() }))
+        .layer(middleware::from_fn_with_state(data.clone(), logger))
+        .with_state(data);
+    let server = TestServer::new(app).unwrap();
+
+    let response = server
+        .get("/")
+        .add_header(
+            HeaderName::from_static("x-request-id"),
+            HeaderValue::from_static("abc-123"),
+        )
+        .await;
+    let body: serde_json::Value = response.json();
+
+    response.assert_status_bad_request();
+    assert_eq!(response.header("x-request-id"), "abc-123");
+    assert_eq!(body["error"]["code"], "bad_request");
+    assert_eq!(body["error"]["request_id"], "abc-123");
+
+    // Anything that doesn't look like an identifier gets replaced
+    let response = server
+        .get("/")
+        .add_header(
+            HeaderName::from_static("x-request-id"),
+            HeaderValue::from_static("a b"),
+        )
+        .await;
+    let body: serde_json::Value = response.json();
+
+    assert_ne!(body["error"]["request_id"], "a b");
+    assert_eq!(
+        response.header("x-request-id").to_str().unwrap(),
+        body["error"]["request_id"]
+    );
+}
+
+#[tokio::test]
+async fn tokens() {
+    let mut config = Settings::new().unwrap();
+    config.api.auth = true;
+    config.api.tokens = vec![
+        Token {
+            name: String::from("dashboard"),
+            token: String::from("secret"),
+            scopes: vec![Scope::User],
+            rate_limit: 1,
+        },
+        Token {
+            name: String::from("feeds"),
+            token: String::from("other"),
+            scopes: vec![Scope::Post],
+            rate_limit: 0,
+        },
+        Token {
+            name: String::from("batcher"),
+            token: String::from("batch"),
+            scopes: vec![Scope::User, Scope::Post],
+            rate_limit: 2,
+        },
+    ];
+
+    let data = Arc::new(ShoelaceData {
+        tokens: Arc::new(Tokens::new(&config.api).unwrap()),
+        config,
+        ..TEST_APP_DATA.clone()
+    });
+    let app = Router::new()
+        .nest(
+            "/api/",
+            api::attach(true).layer(middleware::from_fn_with_state(data.clone(), auth::api)),
+        )
+        .layer(middleware::from_fn_with_state(data.clone(), logger))
+        .with_state(data.clone());
+    let server = TestServer::new(app).unwrap();
+
+    // The OpenAPI document doesn't need a token
+    server.get("/api/openapi.json").await.assert_status_ok();
+
+    let response = server.get("/api/v1/user/zuck").await;
+    response.assert_status_unauthorized();
+    assert_eq!(response.header("www-authenticate"), "Bearer");
+
+    server
+        .get("/api/v1/user/zuck")
+        .authorization_bearer("wrong")
+        .await
+        .assert_status_unauthorized();
+
+    let response = server
+        .get("/api/v1/user/zuck")
+        .authorization_bearer("other")
+        .await;
+    let body: serde_json::Value = response.json();
+
+    response.assert_status_forbidden();
+    assert_eq!(body["error"]["code"], "forbidden");
+
+    // Batches check every scope they need
+    server
+        .post("/api/v1/batch")
+        .json(&json!({ "users": ["zuck"] }))
+        .await
+        .assert_status_unauthorized();
+
+    server
+        .post("/api/v1/batch")
+        .authorization_bearer("other")
+        .json(&json!({ "users": ["zuck"], "posts": [] }))
+        .await
+        .assert_status_forbidden();
+
+    server
+        .post("/api/v1/batch")
+        .json(&json!({}))
+        .await
+        .assert_status_unauthorized();
+
+    // Every item counts against the token's limit, so batches can't hold more than it allows
+    server
+        .post("/api/v1/batch")
+        .authorization_bearer("batch")
+        .json(&json!({ "users": ["zuck", "mosseri", "instagram"] }))
+        .await
+        .assert_status_bad_request();
+
+    let client = Client {
+        name: String::from("charged"),
+        scopes: vec![],
+        rate_limit: 2,
+    };
+
+    auth::charge(Some(&client), 2, &data).await.unwrap();
+    assert!(matches!(
+        auth::charge(Some(&client), 1, &data).await,
+        Err(Error::RateLimited(_))
+    ));
+
+    // Only one request per minute is allowed for this token
+    server
+        .get("/api/openapi.json")
+        .authorization_bearer("secret")
+        .await
+        .assert_status_ok();
+
+    let response = server
+        .get("/api/openapi.json")
+        .authorization_bearer("secret")
+        .await;
+    let body: serde_json::Value = response.json();
+
+    assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["error"]["code"], "rate_limited");
+    assert!(
+        response
+            .header("retry-after")
+            .to_str()
+            .unwrap()
+            .parse::<u64>()
+            .unwrap()
+            > 0
+    );
+}
+
+#[tokio::test]
+async fn limits() {
+    let mut config = Settings::new().unwrap();
+    config.limits.frontend.rate = 1;
+    config.limits.frontend.burst = 2;
+    config.limits.trust_forwarded = true;
+
+    let data = Arc::new(ShoelaceData {
+        config,
+        clients: Limiter::default(),
+        inflight: Inflight::default(),
+        ..TEST_APP_DATA.clone()
+    });
+    let app = Router::new()
+        .route("/", get(|| async { "ok" }))
+        .layer(middleware::from_fn_with_state(data.clone(), limiter))
+        .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 8080))))
+        .with_state(data);
+    let server = TestServer::new(app).unwrap();
+
+    server.get("/").await.assert_status_ok();
+    server.get("/").await.assert_status_ok();
+
+    let response = server.get("/").await;
+    assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(
+        response
+            .header("retry-after")
+            .to_str()
+            .unwrap()
+            .parse::<u64>()
+            .unwrap()
+            > 0
+    );
+
+    // Every client gets its own bucket
+    server
+        .get("/")
+        .add_header(
+            HeaderName::from_static("x-forwarded-for"),
+            HeaderValue::from_static("203.0.113.7, 198.51.100.1"),
+        )
+        .await
+        .assert_status_ok();
+
+    // Beyond the cap, the least recently used buckets make way for new ones
+    let limiter = Limiter::default();
+    limiter.check("first", 1, 1).await.unwrap();
+    assert!(limiter.check("first", 1, 1).await.is_err());
+
+    for client in 0..10000 {
+        limiter.check(&client.to_string(), 1, 1).await.unwrap();
+    }
+
+    assert!(limiter.check("first", 1, 1).await.is_ok());
+}
+
+#[tokio::test]
+async fn batch() {
+    let mut config = Settings::new().unwrap();
+    config.api.max_batch = 2;
+
+    let data = Arc::new(ShoelaceData {
+        config,
+        ..TEST_APP_DATA.clone()
+    });
+    let app = Router::new()
+        .nest("/api/", api::attach(true))
+        .with_state(data);
+    let server = TestServer::new(app).unwrap();
+
+    let response = server.post("/api/v1/batch").json(&json!({})).await;
+    let body: serde_json::Value = response.json();
+
+    response.assert_status_ok();
+    assert_eq!(body, json!({ "users": [], "posts": [] }));
+
+    let response = server
+        .post("/api/v1/batch")
+        .json(&json!({ "users": ["zuck", "mosseri"], "posts": ["C8H5tH8yVGf"] }))
+        .await;
+    let body: serde_json::Value = response.json();
+
+    response.assert_status_bad_request();
+    assert_eq!(body["error"]["code"], "bad_request");
+
+    let response = server.post("/api/v1/batch").text("users=zuck").await;
+    let body: serde_json::Value = response.json();
+
+    response.assert_status_bad_request();
+    assert_eq!(body["error"]["code"], "bad_request");
+}
+
+#[tokio::test]
+async fn flights() {
+    let flights: Flights<usize> = Flights::default();
+    let fetches = Arc::new(AtomicUsize::new(0));
+
+    let fetch = |fetches: Arc<AtomicUsize>| async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        Ok(fetches.fetch_add(1, Ordering::SeqCst) + 1)
+    };
+
+    // Concurrent fetches for the same key share a single result
+    let (first, second, other) = tokio::join!(
+        flights.run("zuck", fetch(fetches.clone())),
+        flights.run("zuck", fetch(fetches.clone())),
+        flights.run("mosseri", fetch(fetches.clone())),
+    );
+
+    assert_eq!(first.unwrap(), second.unwrap());
+    assert!(other.is_ok());
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+    // Finished fetches aren't reused
+    flights.run("zuck", fetch(fetches.clone())).await.unwrap();
+    assert_eq!(fetches.load(Ordering::SeqCst), 3);
+
+    let (first, second) = tokio::join!(
+        flights.run("missing", async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Err(Error::NotFound)
+        }),
+        flights.run("missing", async { Ok(0) }),
+    );
+
+    assert_eq!(first.unwrap_err().code(), Code::NotFound);
+    assert_eq!(second.unwrap_err().code(), Code::NotFound);
+}
+
 #[tokio::test]
 async fn proxy() {
     let app = Router::new()
         .nest("/api/", api::attach(true))
         .nest("/proxy/", proxy::attach())
         .with_state(Arc::new(ShoelaceData {
-            store: crate::proxy::Keystore::Internal(Arc::new(Mutex::new(HashMap::new()))),
+            store: Arc::new(Internal::new(0)),
+            media: None,
             base: Base::new().unwrap(),
             config: Settings::new().unwrap(),
+            tokens: Arc::new(Tokens::default()),
+            clients: Limiter::default(),
+            inflight: Inflight::default(),
         }));
     let server = TestServer::new(app).unwrap();
 
@@ -97,3 +518,938 @@ async fn proxy() {
     println!("{:#?}", response);
     assert_eq!(response.status_code(), StatusCode::OK);
 }
+
+#[tokio::test]
+async fn cache() {
+    let data = ShoelaceData {
+        store: Arc::new(Internal::new(0)),
+        media: None,
+        base: Base::new().unwrap(),
+        config: Settings::new().unwrap(),
+        tokens: Arc::new(Tokens::default()),
+        clients: Limiter::default(),
+        inflight: Inflight::default(),
+    };
+
+    let missing: Option<Vec<String>> = cache::get(Kind::User, "zuck", &data).await;
+    assert_eq!(missing, None);
+
+    cache::set(Kind::User, "zuck", &vec!["cached"], &data).await;
+
+    let cached: Option<Vec<String>> = cache::get(Kind::User, "zuck", &data).await;
+    assert_eq!(cached, Some(vec![String::from("cached")]));
+}
+
+#[tokio::test]
+async fn ranges() {
+    assert_eq!(
+        ByteRange::parse("bytes=0-499"),
+        Some(ByteRange::From(0, Some(499)))
+    );
+    assert_eq!(
+        ByteRange::parse("bytes=500-"),
+        Some(ByteRange::From(500, None))
+    );
+    assert_eq!(ByteRange::parse("bytes=-500"), Some(ByteRange::Suffix(500)));
+    assert_eq!(ByteRange::parse("bytes=0-1,4-5"), None);
+    assert_eq!(ByteRange::parse("bytes=10-5"), None);
+
+    assert_eq!(ByteRange::From(0, Some(499)).resolve(1000), Some((0, 499)));
+    assert_eq!(ByteRange::From(500, None).resolve(1000), Some((500, 999)));
+    assert_eq!(
+        ByteRange::From(900, Some(2000)).resolve(1000),
+        Some((900, 999))
+    );
+    assert_eq!(ByteRange::Suffix(200).resolve(1000), Some((800, 999)));
+    assert_eq!(ByteRange::From(1000, None).resolve(1000), None);
+
+    let chunks = stream::iter([Ok::<_, ()>(Bytes::from("hello ")), Ok(Bytes::from("world"))]);
+    let sliced: Result<Vec<Bytes>, ()> = range::slice(chunks, 4, 7).try_collect().await;
+    assert_eq!(sliced.unwrap().concat(), b"o wo");
+}
+
+#[tokio::test]
+async fn media_cache() {
+    let directory = std::env::temp_dir().join("shoelace-media-cache");
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let cache = MediaCache::new(&config::MediaCache {
+        enabled: true,
+        directory: directory.to_string_lossy().to_string(),
+        max_size: 1,
+        ttl: 0,
+    })
+    .await
+    .unwrap();
+
+    assert!(cache.open("hash").await.is_none());
+
+    // PNG signature, so the cached object's type can be identified
+    let media = stream::iter([
+        Ok::<_, ()>(Bytes::from_static(b"\x89PNG\r\n\x1a\n")),
+        Ok(Bytes::from_static(b"\0\0\0\rIHDR")),
+    ]);
+    let validators = Validators {
+        etag: None,
+        last_modified: Some(String::from("Tue, 14 Nov 2023 22:13:20 GMT")),
+    };
+    let served: Vec<Bytes> = cache
+        .tee("hash", media, validators)
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(served.concat().len(), 16);
+
+    let cached = cache.open("hash").await.unwrap();
+    let response = cached.respond(ByteRange::parse("bytes=8-")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()["content-type"], "image/png");
+    assert_eq!(response.headers()["content-range"], "bytes 8-15/16");
+
+    // Upstream's validators are kept, and objects without an entity tag get one from their content
+    assert_eq!(
+        response.headers()[LAST_MODIFIED],
+        "Tue, 14 Nov 2023 22:13:20 GMT"
+    );
+    assert!(response.headers().contains_key(ETAG));
+
+    // Clients going away mid-stream don't leave partial files behind
+    let media = stream::iter([
+        Ok::<_, ()>(Bytes::from_static(b"\x89PNG")),
+        Ok(Bytes::new()),
+    ]);
+    let mut partial = Box::pin(
+        cache
+            .tee("other", media, Validators::default())
+            .await
+            .unwrap(),
+    );
+    partial.next().await;
+    drop(partial);
+
+    assert!(cache.open("other").await.is_none());
+    assert!(!std::fs::read_dir(&directory).unwrap().any(|entry| entry
+        .unwrap()
+        .path()
+        .extension()
+        .is_some_and(|ext| ext == "part")));
+
+    // Validators go along with their objects, whether they're evicted or were left behind
+    cache
+        .store("large", &vec![0; 2 * 1024 * 1024], Validators::default())
+        .await;
+    assert!(cache.open("large").await.is_none());
+    assert!(!directory.join("large.meta").exists());
+
+    cache
+        .store("small", b"\x89PNG\r\n\x1a\n", Validators::default())
+        .await;
+    std::fs::write(directory.join("orphan.meta"), "\n\n").unwrap();
+    MediaCache::new(&config::MediaCache {
+        enabled: true,
+        directory: directory.to_string_lossy().to_string(),
+        max_size: 1,
+        ttl: 0,
+    })
+    .await
+    .unwrap();
+    assert!(!directory.join("orphan.meta").exists());
+    assert!(directory.join("small.meta").exists());
+}
+
+#[tokio::test]
+async fn keystore() {
+    let store = Internal::new(2);
+
+    store.set("first", "1", None).await.unwrap();
+    store.set("second", "2", None).await.unwrap();
+    store.set("third", "3", None).await.unwrap();
+
+    // Least recently used entries are dropped once the store is full
+    assert_eq!(store.get("first").await.unwrap(), None);
+    assert_eq!(store.get("third").await.unwrap(), Some(String::from("3")));
+
+    store
+        .set("expired", "4", Some(Duration::ZERO))
+        .await
+        .unwrap();
+    assert_eq!(store.get("expired").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn sqlite() {
+    let store = Sqlite::new(":memory:").await.unwrap();
+
+    assert_eq!(store.get("key").await.unwrap(), None);
+
+    store.set("key", "value", None).await.unwrap();
+    assert_eq!(store.get("key").await.unwrap(), Some(String::from("value")));
+
+    store
+        .set("key", "expired", Some(Duration::ZERO))
+        .await
+        .unwrap();
+    assert_eq!(store.get("key").await.unwrap(), None);
+}
+
+#[test]
+fn signing() {
+    let mut config = Settings::new().unwrap().proxy;
+    let url = "https://scontent.cdninstagram.com/v/t51.2885-19/pfp.jpg?stp=dst-jpg_s150x150";
+
+    assert!(signing::sign(url, &config).is_err());
+
+    config.secret = Some(String::from("secret"));
+    let id = signing::sign(url, &config).unwrap();
+    assert!(signing::is_signed(&id));
+    assert_eq!(signing::verify(&id, &config).unwrap(), url);
+
+    // Signatures are bound to both the link and the secret
+    let tampered = format!("x{}", id);
+    assert!(signing::verify(&tampered, &config).is_err());
+
+    config.secret = Some(String::from("another secret"));
+    assert!(signing::verify(&id, &config).is_err());
+
+    // The sample secret is public, so it's refused
+    config.secret = Some(String::from("change me"));
+    assert!(signing::check(&config).is_err());
+}
+
+#[tokio::test]
+async fn probes() {
+    let data = ShoelaceData {
+        store: Arc::new(Internal::new(0)),
+        ..TEST_APP_DATA.clone()
+    };
+
+    let link = proxy::store("https://scontent.cdninstagram.com/video.mp4", data.clone())
+        .await
+        .unwrap();
+    let hash = link.rsplit('/').next().unwrap();
+
+    // Earlier lookups are reused, rather than asking the CDN again
+    data.store
+        .set(&format!("probe:{}", hash), "video/mp4\n1234", None)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        proxy::probe(&link, &data).await.unwrap(),
+        (Some(String::from("video/mp4")), Some(1234))
+    );
+}
+
+#[tokio::test]
+async fn signed_keystore() {
+    let mut config = Settings::new().unwrap();
+    config.proxy.secret = Some(String::from("secret"));
+
+    let id = signing::sign("https://scontent.cdninstagram.com/pfp.jpg", &config.proxy).unwrap();
+
+    // Outside of signed mode, signed links are looked up in the keystore, where they don't exist
+    let app = Router::new()
+        .nest("/proxy/", proxy::attach())
+        .with_state(Arc::new(ShoelaceData {
+            store: Arc::new(Internal::new(0)),
+            config,
+            ..TEST_APP_DATA.clone()
+        }));
+    let server = TestServer::new(app).unwrap();
+
+    server
+        .get(&format!("/proxy/{}", id))
+        .await
+        .assert_status_not_found();
+}
+
+#[tokio::test]
+async fn variants() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+
+    let mut image = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::new_rgba8(200, 100)
+        .write_to(&mut image, image::ImageFormat::Png)
+        .unwrap();
+    let image = Bytes::from(image.into_inner());
+    let broken = Bytes::from_static(b"\x89PNG\r\n\x1a\nbroken");
+
+    // Chunked responses, so upstream never says how large the media is
+    let chunked = |chunks: Vec<Bytes>| {
+        Response::builder()
+            .header("content-type", "image/png")
+            .body(Body::from_stream(stream::iter(
+                chunks.into_iter().map(Ok::<_, Error>),
+            )))
+            .unwrap()
+    };
+    let upstream = Router::new()
+        .route("/text", get(|| async { "plain text" }))
+        .route("/image", get(move || async move { chunked(vec![image]) }))
+        .route("/broken", get(move || async move { chunked(vec![broken]) }))
+        .route(
+            "/large",
+            get(move || async move {
+                let mut chunks = vec![Bytes::from_static(b"\x89PNG\r\n\x1a\n")];
+                chunks.extend((0..40).map(|_| Bytes::from(vec![0; 1024 * 1024])));
+                chunked(chunks)
+            }),
+        )
+        .layer(middleware::from_fn({
+            let fetches = fetches.clone();
+            move |request, next: middleware::Next| {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                next.run(request)
+            }
+        }));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+    let directory = std::env::temp_dir().join("shoelace-variants");
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let mut config = Settings::new().unwrap();
+    config.proxy.allowed_hosts = Vec::new();
+    let data = ShoelaceData {
+        store: Arc::new(Internal::new(0)),
+        media: Some(
+            MediaCache::new(&config::MediaCache {
+                enabled: true,
+                directory: directory.to_string_lossy().to_string(),
+                max_size: 1,
+                ttl: 0,
+            })
+            .await
+            .unwrap(),
+        ),
+        config,
+        ..TEST_APP_DATA.clone()
+    };
+
+    let mut links = Vec::new();
+    for path in ["text", "image", "large", "broken"] {
+        let url = format!("http://{}/{}", address, path);
+        links.push(proxy::store(&url, data.clone()).await.unwrap());
+    }
+
+    let server = TestServer::new(
+        Router::new()
+            .nest("/proxy/", proxy::attach())
+            .with_state(Arc::new(data)),
+    )
+    .unwrap();
+
+    // Media which can't be transformed is served as is, without being fetched twice
+    let response = server.get(&format!("{}?w=50", links[0])).await;
+    assert_eq!(response.text(), "plain text");
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    let response = server.get(&format!("{}?w=50&format=jpeg", links[1])).await;
+    assert_eq!(response.headers()["content-type"], "image/jpeg");
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+    // Variants are tagged by their content, and revalidated from the cache
+    let etag = response.headers()[ETAG].clone();
+    server
+        .get(&format!("{}?w=50&format=jpeg", links[1]))
+        .add_header(IF_NONE_MATCH, etag)
+        .await
+        .assert_status(StatusCode::NOT_MODIFIED);
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+    // Objects which don't exist aren't reported as unchanged
+    server
+        .get("/proxy/missing")
+        .add_header(IF_NONE_MATCH, HeaderValue::from_static("*"))
+        .await
+        .assert_status_not_found();
+
+    // Images beyond the transform limit are streamed untransformed, rather than read into memory
+    let response = server.get(&format!("{}?w=50", links[2])).await;
+    assert_eq!(response.headers()["content-type"], "image/png");
+    assert_eq!(response.as_bytes().len(), 8 + 40 * 1024 * 1024);
+    assert_eq!(fetches.load(Ordering::SeqCst), 3);
+
+    // Images which can't be decoded are served as they are, rather than failing
+    let response = server.get(&format!("{}?w=50", links[3])).await;
+    response.assert_status_ok();
+    assert_eq!(response.headers()["content-type"], "image/png");
+    assert_eq!(response.as_bytes().as_ref(), b"\x89PNG\r\n\x1a\nbroken");
+}
+
+#[tokio::test]
+async fn revalidation() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+
+    let upstream = Router::new()
+        .route(
+            "/text",
+            get(|| async { ([(ETAG, "W/\"upstream\"")], "plain text") }),
+        )
+        .layer(middleware::from_fn({
+            let fetches = fetches.clone();
+            move |request, next: middleware::Next| {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                next.run(request)
+            }
+        }));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+    let mut config = Settings::new().unwrap();
+    config.proxy.allowed_hosts = Vec::new();
+    let data = ShoelaceData {
+        store: Arc::new(Internal::new(0)),
+        media: None,
+        config,
+        ..TEST_APP_DATA.clone()
+    };
+
+    let link = proxy::store(&format!("http://{}/text", address), data.clone())
+        .await
+        .unwrap();
+
+    let server = TestServer::new(
+        Router::new()
+            .nest("/proxy/", proxy::attach())
+            .with_state(Arc::new(data)),
+    )
+    .unwrap();
+
+    // Upstream's tags aren't passed through
+    let response = server.get(&link).await;
+    assert_eq!(response.text(), "plain text");
+    assert!(response.headers().get(ETAG).is_none());
+
+    // Once served, objects are tagged by their content, and revalidated without going upstream
+    let response = server.get(&link).await;
+    let etag = response.headers()[ETAG].clone();
+    assert!(!etag.to_str().unwrap().starts_with("W/"));
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+    server
+        .get(&link)
+        .add_header(IF_NONE_MATCH, etag)
+        .await
+        .assert_status(StatusCode::NOT_MODIFIED);
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn redirects() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let redirect = |to: String| {
+        Response::builder()
+            .status(StatusCode::FOUND)
+            .header("location", to)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let upstream =
+        Router::new()
+            .route("/text", get(|| async { "plain text" }))
+            .route(
+                "/allowed",
+                get(move || async move { redirect(format!("http://{}/text", address)) }),
+            )
+            .route(
+                "/elsewhere",
+                get(move || async move {
+                    redirect(format!("http://localhost:{}/text", address.port()))
+                }),
+            );
+    tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+    let mut config = Settings::new().unwrap();
+    config.proxy.allowed_hosts = vec![String::from("127.0.0.1")];
+    let data = ShoelaceData {
+        store: Arc::new(Internal::new(0)),
+        config,
+        ..TEST_APP_DATA.clone()
+    };
+
+    let allowed = proxy::store(&format!("http://{}/allowed", address), data.clone())
+        .await
+        .unwrap();
+    let elsewhere = proxy::store(&format!("http://{}/elsewhere", address), data.clone())
+        .await
+        .unwrap();
+
+    let server = TestServer::new(
+        Router::new()
+            .nest("/proxy/", proxy::attach())
+            .with_state(Arc::new(data)),
+    )
+    .unwrap();
+
+    // Redirects are only followed to hosts which are allowed themselves
+    assert_eq!(server.get(&allowed).await.text(), "plain text");
+    server
+        .get(&elsewhere)
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn forbidden_media() {
+    let data = ShoelaceData {
+        store: Arc::new(Internal::new(0)),
+        ..TEST_APP_DATA.clone()
+    };
+
+    // Media on other hosts is blanked, rather than failing the whole response
+    assert_eq!(
+        req::proxied("https://example.com/pfp.jpg", &data)
+            .await
+            .unwrap(),
+        ""
+    );
+    assert!(
+        req::proxied("https://scontent.cdninstagram.com/pfp.jpg", &data)
+            .await
+            .unwrap()
+            .contains("/proxy/")
+    );
+}
+
+#[test]
+fn allowed_hosts() {
+    let patterns = vec![
+        String::from("*.cdninstagram.com"),
+        String::from("fbcdn.net"),
+    ];
+
+    assert!(allowlist::check(
+        "https://scontent-lax3-1.cdninstagram.com/v/a.jpg",
+        &patterns
+    )
+    .is_ok());
+    assert!(allowlist::check("https://fbcdn.net/a.jpg", &patterns).is_ok());
+    assert!(allowlist::check("https://cdninstagram.com/a.jpg", &patterns).is_err());
+    assert!(allowlist::check("https://evilcdninstagram.com/a.jpg", &patterns).is_err());
+    assert!(allowlist::check("https://example.com/a.jpg", &patterns).is_err());
+    assert!(allowlist::check("file:///etc/passwd", &patterns).is_err());
+    assert!(allowlist::check("https://example.com/a.jpg", &[]).is_ok());
+}
+
+#[test]
+fn transform() {
+    let mut source = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::new_rgba8(200, 100)
+        .write_to(&mut source, image::ImageFormat::Png)
+        .unwrap();
+
+    let transform = Transform {
+        w: Some(50),
+        h: None,
+        format: Some(Format::Jpeg),
+    };
+    let (output, mime) = transform.apply(source.get_ref()).unwrap();
+    let output = image::load_from_memory(&output).unwrap();
+
+    assert_eq!(mime, "image/jpeg");
+    assert_eq!((output.width(), output.height()), (96, 48));
+    assert_eq!(transform.key("hash"), "hash.96x0.jpeg");
+
+    // Sizes are rounded up to a supported one, up to the largest
+    let transform = Transform {
+        w: Some(100_000),
+        h: Some(1),
+        format: None,
+    };
+    assert_eq!(transform.key("hash"), "hash.1536x48.auto");
+}
+
+#[test]
+fn bodies() {
+    let base = Base::new().unwrap();
+    let body = frontend::formatters::body(
+        "<script>alert(1)</script> https://example.com/?a=1&b=2 @zuck\"",
+        &base,
+    )
+    .unwrap();
+
+    // Upstream markup is escaped, while links and mentions added here aren't
+    assert_eq!(
+        body,
+        "&lt;script&gt;alert(1)&lt;/script&gt; \
+         <a href=\"https://example.com/?a=1&amp;b=2\">example.com/?a=1&amp;b=2</a> \
+         <a href=\"http://localhost:8080/@zuck&quot;\">@zuck&quot;</a>"
+    );
+}
+
+#[test]
+fn conditional() {
+    let etag = conditional::etag("hash");
+    let mut headers = HeaderMap::new();
+    assert!(!conditional::none_match(&headers, &etag));
+
+    headers.insert(
+        IF_NONE_MATCH,
+        HeaderValue::from_static("\"other\", W/\"hash\""),
+    );
+    assert!(conditional::none_match(&headers, &etag));
+
+    // Responses are only turned into a 304 if they weren't modified since the client's copy
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let response = || {
+        Response::builder()
+            .header(LAST_MODIFIED, conditional::http_date(modified))
+            .header(ETAG, &etag)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        IF_MODIFIED_SINCE,
+        HeaderValue::from_str(&conditional::http_date(modified)).unwrap(),
+    );
+    let finished = conditional::finish(response(), &headers, conditional::IMMUTABLE);
+    assert_eq!(finished.status(), StatusCode::NOT_MODIFIED);
+
+    headers.insert(
+        IF_MODIFIED_SINCE,
+        HeaderValue::from_str(&conditional::http_date(modified - Duration::from_secs(60))).unwrap(),
+    );
+    let finished = conditional::finish(response(), &headers, conditional::IMMUTABLE);
+    assert_eq!(finished.status(), StatusCode::OK);
+    assert_eq!(finished.headers()[ETAG], "\"hash\"");
+
+    // Any tag matches objects which exist, but If-None-Match rules out If-Modified-Since
+    headers.insert(IF_NONE_MATCH, HeaderValue::from_static("*"));
+    let finished = conditional::finish(response(), &headers, conditional::IMMUTABLE);
+    assert_eq!(finished.status(), StatusCode::NOT_MODIFIED);
+
+    headers.insert(IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+    headers.insert(
+        IF_MODIFIED_SINCE,
+        HeaderValue::from_str(&conditional::http_date(modified)).unwrap(),
+    );
+    let finished = conditional::finish(response(), &headers, conditional::IMMUTABLE);
+    assert_eq!(finished.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn merged_limits() {
+    let mut data = TEST_APP_DATA.clone();
+    data.config.feeds.max_users = 2;
+    data.config.feeds.lists.insert(
+        String::from("team"),
+        vec![String::from("a"), String::from("b"), String::from("c")],
+    );
+
+    // Limits are checked before anything gets fetched
+    let too_many = Feed::merged("a,b,c", &data).await;
+    assert!(matches!(too_many, Err(Error::TooManyUsers(2))));
+
+    let listed = Feed::merged("team", &data).await;
+    assert!(matches!(listed, Err(Error::TooManyUsers(2))));
+
+    let empty = Feed::merged(" , ", &data).await;
+    assert!(matches!(empty, Err(Error::NotFound)));
+}
+
+#[test]
+fn filters() {
+    let posts = vec![
+        (post("a", 1, "Launching Threads", true), ()),
+        (post("b", 2, "Working on AI", false), ()),
+        (post("c", 3, "More AI news", true), ()),
+        (post("d", 4, "Fencing practice", true), ()),
+    ];
+    let codes = |posts: &[(Subpost, ())]| {
+        posts
+            .iter()
+            .map(|(post, _)| post.code.clone())
+            .collect::<Vec<String>>()
+    };
+
+    let mut filtered = posts.clone();
+    Filters {
+        media_only: true,
+        include: Some(String::from("ai, threads")),
+        exclude: Some(String::from("NEWS")),
+        limit: None,
+    }
+    .apply(&mut filtered);
+    assert_eq!(codes(&filtered), vec!["a"]);
+
+    let mut limited = posts.clone();
+    Filters {
+        media_only: true,
+        limit: Some(2),
+        ..Default::default()
+    }
+    .apply(&mut limited);
+    assert_eq!(codes(&limited), vec!["d", "c"]);
+}
+
+#[tokio::test]
+async fn feed_validators() {
+    let older = || {
+        feed(
+            vec![post("a", 1_700_000_000, "Launching Threads", false)],
+            &TEST_APP_DATA,
+        )
+    };
+    let newer = || {
+        feed(
+            vec![
+                post("b", 1_700_000_060, "Working on AI", false),
+                post("a", 1_700_000_000, "Launching Threads", false),
+            ],
+            &TEST_APP_DATA,
+        )
+    };
+
+    // Tags differ between formats, and change along with the feed's posts
+    let etag = older().etag(rss::Format::Rss);
+    assert_eq!(etag, older().etag(rss::Format::Rss));
+    assert_ne!(etag, older().etag(rss::Format::Atom));
+    assert_ne!(etag, newer().etag(rss::Format::Rss));
+
+    assert_eq!(
+        older().last_modified().as_deref(),
+        Some("Tue, 14 Nov 2023 22:13:20 GMT")
+    );
+    assert_eq!(
+        newer().last_modified().as_deref(),
+        Some("Tue, 14 Nov 2023 22:14:20 GMT")
+    );
+    assert_eq!(feed(Vec::new(), &TEST_APP_DATA).last_modified(), None);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(IF_NONE_MATCH, HeaderValue::from_str(&etag).unwrap());
+    let respond = |feed: Feed, headers: HeaderMap, data: ShoelaceData| async move {
+        rss::respond(
+            rss::Format::Rss,
+            Ok(feed),
+            &Filters::default(),
+            &headers,
+            &data,
+        )
+        .await
+    };
+
+    // Clients holding the current feed don't get it again
+    let response = respond(older(), headers.clone(), TEST_APP_DATA.clone()).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], etag.as_str());
+    assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=300");
+
+    // Once a new post appears, their copy is stale
+    let response = respond(newer(), headers.clone(), TEST_APP_DATA.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[ETAG],
+        newer().etag(rss::Format::Rss).as_str()
+    );
+    assert_eq!(
+        response.headers()[LAST_MODIFIED],
+        "Tue, 14 Nov 2023 22:14:20 GMT"
+    );
+    assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=300");
+
+    let mut data = TEST_APP_DATA.clone();
+    data.config.feeds.max_age = 0;
+    let response = respond(newer(), HeaderMap::new(), data).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+}
+
+#[tokio::test]
+async fn titles() {
+    // Titles come from the first line with any text, shortened to fit
+    assert_eq!(
+        rss::title("\n  \nLaunching Threads\nMore soon").as_deref(),
+        Some("Launching Threads")
+    );
+    assert_eq!(rss::title(" \n\t\n"), None);
+    assert_eq!(rss::title(""), None);
+
+    let exact = "a".repeat(80);
+    assert_eq!(rss::title(&exact), Some(exact));
+
+    let long = format!("{} {}", "a".repeat(78), "b".repeat(10));
+    assert_eq!(rss::title(&long), Some(format!("{}…", "a".repeat(78))));
+    assert_eq!(rss::title(&"é".repeat(100)).unwrap().chars().count(), 80);
+
+    // Posts without text fall back to their author and date, and GUIDs always point to Threads
+    let posts = vec![
+        post("a", 1_700_000_000, "Launching Threads\nMore soon", false),
+        post("b", 1_700_000_000, "", false),
+    ];
+    let mut data = TEST_APP_DATA.clone();
+    data.config.endpoint.frontend = false;
+
+    let rendered = rendered(rss::Format::Rss, feed(posts, &data), &data).await;
+    assert!(rendered.contains("<title>Launching Threads</title>"));
+    assert!(rendered.contains("<title>Post by Mark Zuckerberg on 2023-11-14</title>"));
+    assert!(rendered.contains("<guid>https://www.threads.net/@zuck/post/a</guid>"));
+    assert!(rendered.contains("<link>https://www.threads.net/@zuck/post/b</link>"));
+}
+
+#[tokio::test]
+async fn formats() {
+    let mut data = TEST_APP_DATA.clone();
+    data.config.endpoint.frontend = false;
+    let posts = || vec![post("a", 1_700_000_000, "Launching Threads", false)];
+
+    let atom = rendered(rss::Format::Atom, feed(posts(), &data), &data).await;
+    assert!(atom.contains("<id>https://www.threads.net/@zuck/post/a</id>"));
+    assert!(atom.contains("<updated>2023-11-14T22:13:20+00:00</updated>"));
+    assert!(atom
+        .contains("<author><name>@zuck</name><uri>https://www.threads.net/@zuck</uri></author>"));
+    assert!(atom.contains("href=\"http://localhost:8080/atom/zuck\""));
+
+    let document: serde_json::Value =
+        serde_json::from_str(&rendered(rss::Format::Json, feed(posts(), &data), &data).await)
+            .unwrap();
+    assert_eq!(document["version"], "https://jsonfeed.org/version/1.1");
+    assert_eq!(document["feed_url"], "http://localhost:8080/feed/zuck");
+    assert_eq!(
+        document["items"],
+        json!([{
+            "id": "https://www.threads.net/@zuck/post/a",
+            "url": "https://www.threads.net/@zuck/post/a",
+            "title": "Launching Threads",
+            "content_html": "<p>Launching Threads</p>",
+            "content_text": "Launching Threads",
+            "date_published": "2023-11-14T22:13:20+00:00",
+            "authors": [{ "name": "@zuck", "url": "https://www.threads.net/@zuck" }],
+        }])
+    );
+}
+
+#[tokio::test]
+async fn enclosures() {
+    let data = ShoelaceData {
+        store: Arc::new(Internal::new(0)),
+        ..TEST_APP_DATA.clone()
+    };
+
+    let link = proxy::store("https://scontent.cdninstagram.com/pfp.png", data.clone())
+        .await
+        .unwrap();
+    let hash = link.rsplit('/').next().unwrap();
+    data.store
+        .set(&format!("probe:{}", hash), "image/png\n1234", None)
+        .await
+        .unwrap();
+
+    let posts = || {
+        let mut post = post("a", 1_700_000_000, "Launching Threads", true);
+        post.media[0].content = link.clone();
+        post.media[0].alt = Some(String::from("Threads logo"));
+        vec![post]
+    };
+
+    // Media is described by what the proxy found, each format in its own way
+    let channel = rendered(rss::Format::Rss, feed(posts(), &data), &data).await;
+    assert!(channel.contains(&format!(
+        "<enclosure url=\"{}\" length=\"1234\" type=\"image/png\"/>",
+        link
+    )));
+    assert!(channel.contains("<media:description>Threads logo</media:description>"));
+    assert!(channel.contains(&format!("<img src=\"{}\"", link)));
+
+    let atom = rendered(rss::Format::Atom, feed(posts(), &data), &data).await;
+    assert!(atom.contains(&format!(
+        "<link href=\"{}\" rel=\"enclosure\" type=\"image/png\" title=\"Threads logo\" length=\"1234\"/>",
+        link
+    )));
+
+    let document: serde_json::Value =
+        serde_json::from_str(&rendered(rss::Format::Json, feed(posts(), &data), &data).await)
+            .unwrap();
+    assert_eq!(
+        document["items"][0]["attachments"],
+        json!([{
+            "url": link,
+            "mime_type": "image/png",
+            "title": "Threads logo",
+            "size_in_bytes": 1234,
+        }])
+    );
+}
+
+#[test]
+fn threads() {
+    let reply = |code: &str, date: u64| {
+        let mut reply = post(code, date, "Welcome!", false);
+        reply.author.username = String::from("mosseri");
+        reply
+    };
+    let select = |options: ThreadOptions| {
+        options
+            .select(
+                post("b", 2, "Launching Threads", false),
+                vec![post("a", 1, "Coming soon", false)],
+                vec![reply("c", 3), reply("d", 4)],
+            )
+            .into_iter()
+            .map(|(post, label)| (post.code, label))
+            .collect::<Vec<(String, String)>>()
+    };
+    let entry = |code: &str, label: &str| (code.to_string(), label.to_string());
+
+    // Parents are only included when asked for, ahead of the post itself
+    assert_eq!(
+        select(ThreadOptions::default()),
+        vec![
+            entry("b", "Post by @zuck"),
+            entry("c", "Reply by @mosseri"),
+            entry("d", "Reply by @mosseri"),
+        ]
+    );
+    assert_eq!(
+        select(ThreadOptions {
+            parents: true,
+            ..Default::default()
+        }),
+        vec![
+            entry("a", "Post by @zuck"),
+            entry("b", "Post by @zuck"),
+            entry("c", "Reply by @mosseri"),
+            entry("d", "Reply by @mosseri"),
+        ]
+    );
+
+    // Replies can be left out, leaving just the post
+    assert_eq!(
+        select(ThreadOptions {
+            replies: false,
+            ..Default::default()
+        }),
+        vec![entry("b", "Post by @zuck")]
+    );
+}
+
+#[test]
+fn opml() {
+    let document = opml::document("zuck,mosseri", &TEST_APP_DATA).unwrap();
+    assert!(document.contains("xmlUrl=\"http://localhost:8080/rss/zuck\""));
+    assert_eq!(opml::usernames(&document).unwrap(), vec!["mosseri", "zuck"]);
+
+    // Feeds from elsewhere are recognized by their profile links, and anything else is skipped
+    let imported = opml::usernames(
+        r#"<opml version="2.0"><body>
+            <outline text="Blog" xmlUrl="https://example.com/index.xml"/>
+            <outline text="Threads"><outline text="@zuck" htmlUrl="https://www.threads.net/@zuck"/></outline>
+            <outline text="JSON" xmlUrl="https://shoelace.example/feed/mosseri.json?limit=5"/>
+        </body></opml>"#,
+    )
+    .unwrap();
+    assert_eq!(imported, vec!["mosseri", "zuck"]);
+
+    assert!(opml::usernames("<opml><body></opml>").is_err());
+}